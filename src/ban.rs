@@ -0,0 +1,273 @@
+//! Client de l'API Adresse (BAN) : recherche unitaire et géocodage CSV par lot
+//!
+//! Une instance Addok auto-hébergée expose la même API et passe par ce client.
+
+use crate::geocodeur::{Correspondance, ErreurGeocodage, Geocodeur, Requete, url_de_base};
+use crate::reseau::ClientHttp;
+use reqwest::Url;
+use reqwest::blocking::multipart;
+use serde::Deserialize;
+
+/// Adresse de l'API publique, remplaçable pour viser une instance locale
+pub const URL_API_PUBLIQUE: &str = "https://api-adresse.data.gouv.fr";
+
+/// Débit par défaut vers l'API publique, limitée à 50 requêtes par seconde et par IP
+pub const DEBIT_API_PUBLIQUE: f64 = 30.0;
+
+/// Réponse GeoJSON de l'API (seuls les champs utiles sont lus)
+#[derive(Deserialize)]
+struct ReponseApi {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometrie,
+    properties: Correspondance,
+}
+
+#[derive(Deserialize)]
+struct Geometrie {
+    coordinates: [f64; 2],
+}
+
+/// Ligne du CSV renvoyé par /search/csv/ : colonnes envoyées suivies des colonnes result_*
+#[derive(Deserialize)]
+struct LigneLot {
+    id_ligne: usize,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    #[serde(default)]
+    result_label: String,
+    result_score: Option<f64>,
+    #[serde(default)]
+    result_type: String,
+    #[serde(default)]
+    result_id: String,
+    #[serde(default)]
+    result_citycode: String,
+    #[serde(default)]
+    result_postcode: String,
+    #[serde(default)]
+    result_city: String,
+    #[serde(default)]
+    result_housenumber: String,
+    #[serde(default)]
+    result_street: String,
+}
+
+impl LigneLot {
+    fn en_correspondance(self) -> Option<Correspondance> {
+        let score = self.result_score?;
+        if self.result_label.is_empty() {
+            return None;
+        }
+        Some(Correspondance {
+            label: self.result_label,
+            score,
+            type_resultat: self.result_type,
+            id: self.result_id,
+            citycode: self.result_citycode,
+            postcode: Some(self.result_postcode).filter(|cp| !cp.is_empty()),
+            city: self.result_city,
+            housenumber: Some(self.result_housenumber).filter(|numero| !numero.is_empty()),
+            street: Some(self.result_street).filter(|voie| !voie.is_empty()),
+            longitude: self.longitude.unwrap_or_default(),
+            latitude: self.latitude.unwrap_or_default(),
+        })
+    }
+}
+
+/// Client HTTP de l'API Adresse
+pub struct ClientBan {
+    http: ClientHttp,
+    base: Url,
+}
+
+impl ClientBan {
+    pub fn new(base: &str, http: ClientHttp) -> Result<Self, ErreurGeocodage> {
+        Ok(ClientBan {
+            http,
+            base: url_de_base(base)?,
+        })
+    }
+}
+
+impl Geocodeur for ClientBan {
+    /// Recherche unitaire via /search/
+    fn geocoder(
+        &self,
+        requete: &Requete,
+        limite: usize,
+    ) -> Result<Vec<Correspondance>, ErreurGeocodage> {
+        let mut url = self.base.join("search/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("q", &requete.texte())
+            .append_pair("limit", &limite.to_string());
+
+        let reponse = self
+            .http
+            .envoyer(|| self.http.get(url.clone()))?
+            .json::<ReponseApi>()?;
+
+        Ok(reponse
+            .features
+            .into_iter()
+            .map(|feature| {
+                let [longitude, latitude] = feature.geometry.coordinates;
+                Correspondance {
+                    longitude,
+                    latitude,
+                    ..feature.properties
+                }
+            })
+            .collect())
+    }
+
+    /// Géocodage d'un lot d'adresses via /search/csv/
+    fn geocoder_lot(
+        &self,
+        requetes: &[Requete],
+    ) -> Result<Vec<Option<Correspondance>>, ErreurGeocodage> {
+        // Le numéro de ligne n'est pas utilisé pour la recherche : il permet de
+        // remettre les résultats dans l'ordre d'entrée
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.write_record(["id_ligne", "adresse", "cp", "ville"])?;
+        for (i, requete) in requetes.iter().enumerate() {
+            wtr.write_record([&i.to_string(), requete.adresse, requete.cp, requete.ville])?;
+        }
+        // L'écriture se fait en mémoire et ne peut pas échouer
+        let donnees = wtr.into_inner().expect("écriture en mémoire");
+
+        // Le formulaire est reconstruit à chaque tentative
+        let formulaire = || {
+            let fichier = multipart::Part::bytes(donnees.clone())
+                .file_name("adresses.csv")
+                .mime_str("text/csv")
+                .unwrap();
+            multipart::Form::new()
+                .part("data", fichier)
+                .text("columns", "adresse")
+                .text("columns", "cp")
+                .text("columns", "ville")
+        };
+
+        let url = self.base.join("search/csv/").unwrap();
+        let reponse = self
+            .http
+            .envoyer(|| self.http.post(url.clone()).multipart(formulaire()))?
+            .bytes()?;
+
+        let mut resultats = vec![None; requetes.len()];
+        let mut rdr = csv::Reader::from_reader(reponse.as_ref());
+        let mut recues = 0;
+        for ligne in rdr.deserialize::<LigneLot>() {
+            let ligne = ligne?;
+            recues += 1;
+            let emplacement = resultats.get_mut(ligne.id_ligne).ok_or_else(|| {
+                ErreurGeocodage::Reponse(format!("ligne inattendue : {}", ligne.id_ligne))
+            })?;
+            *emplacement = ligne.en_correspondance();
+        }
+        if recues != requetes.len() {
+            return Err(ErreurGeocodage::Reponse(format!(
+                "{recues} lignes reçues pour {} envoyées",
+                requetes.len()
+            )));
+        }
+        Ok(resultats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serveur d'une seule requête, qui répond `corps` et renvoie la requête reçue
+    fn serveur(corps: &'static str) -> (String, thread::JoinHandle<String>) {
+        let ecoute = TcpListener::bind("127.0.0.1:0").unwrap();
+        let adresse = format!("http://{}", ecoute.local_addr().unwrap());
+        let fil = thread::spawn(move || {
+            let (mut flux, _) = ecoute.accept().unwrap();
+            let mut recu = Vec::new();
+            let mut tampon = [0; 4096];
+            // Le formulaire se termine par la limite suivie de « -- »
+            while !(recu.windows(4).any(|f| f == b"\r\n\r\n") && recu.ends_with(b"--\r\n")) {
+                let lus = flux.read(&mut tampon).unwrap();
+                if lus == 0 {
+                    break;
+                }
+                recu.extend_from_slice(&tampon[..lus]);
+            }
+            write!(
+                flux,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{corps}",
+                corps.len()
+            )
+            .unwrap();
+            String::from_utf8_lossy(&recu).into_owned()
+        });
+        (adresse, fil)
+    }
+
+    #[test]
+    fn lot_remis_dans_l_ordre() {
+        let (adresse, fil) = serveur(
+            "id_ligne,adresse,cp,ville,latitude,longitude,result_label,result_score,result_type,result_id,result_citycode,result_postcode,result_city,result_housenumber,result_street\n\
+             2,3 rue Nationale,59000,Lille,50.63,3.05,3 Rue Nationale 59000 Lille,0.95,housenumber,59350_6540_00003,59350,59000,Lille,3,Rue Nationale\n\
+             0,12 rue de la Paix,75002,Paris,48.87,2.33,12 Rue de la Paix 75002 Paris,0.97,housenumber,75102_7000_00012,75102,75002,Paris,12,Rue de la Paix\n\
+             1,rue inconnue,59000,Lille,,,,,,,,,,,\n",
+        );
+        let client = ClientBan::new(&adresse, ClientHttp::new(None, 0).unwrap()).unwrap();
+        let requetes = [
+            Requete {
+                adresse: "12 rue de la Paix",
+                cp: "75002",
+                ville: "Paris",
+            },
+            Requete {
+                adresse: "rue inconnue",
+                cp: "59000",
+                ville: "Lille",
+            },
+            Requete {
+                adresse: "3 rue Nationale",
+                cp: "59000",
+                ville: "Lille",
+            },
+        ];
+        let resultats = client.geocoder_lot(&requetes).unwrap();
+        let requete = fil.join().unwrap();
+
+        assert!(requete.starts_with("POST /search/csv/"));
+        assert!(requete.contains("id_ligne,adresse,cp,ville\n0,12 rue de la Paix,75002,Paris"));
+        assert_eq!(requete.matches("name=\"columns\"").count(), 3);
+        assert_eq!(resultats.len(), 3);
+        let paix = resultats[0].as_ref().unwrap();
+        assert_eq!((paix.id.as_str(), paix.score), ("75102_7000_00012", 0.97));
+        assert_eq!(paix.street.as_deref(), Some("Rue de la Paix"));
+        assert!(resultats[1].is_none());
+        let nationale = resultats[2].as_ref().unwrap();
+        assert_eq!(nationale.postcode.as_deref(), Some("59000"));
+        assert_eq!((nationale.longitude, nationale.latitude), (3.05, 50.63));
+    }
+
+    #[test]
+    fn lot_incomplet_refuse() {
+        let (adresse, fil) = serveur(
+            "id_ligne,adresse,cp,ville,result_label,result_score\n0,rue A,59000,Lille,Rue A 59000 Lille,0.9\n",
+        );
+        let client = ClientBan::new(&adresse, ClientHttp::new(None, 0).unwrap()).unwrap();
+        let requete = Requete {
+            adresse: "rue A",
+            cp: "59000",
+            ville: "Lille",
+        };
+        let resultat = client.geocoder_lot(&[requete, requete]);
+        fil.join().unwrap();
+        assert!(matches!(resultat, Err(ErreurGeocodage::Reponse(_))));
+    }
+}
@@ -0,0 +1,341 @@
+//! Lecture des adresses dans une base SQLite et écriture des résultats dans la même base
+//!
+//! L'entrée est désignée par « sqlite:chemin?table=clients » ou
+//! « sqlite:chemin?query=SELECT … », la requête étant écrite telle quelle
+//! (sans encodage d'URL). Les résultats vont dans une nouvelle table,
+//! ou complètent la table source ligne par ligne via sa clé.
+
+use crate::sortie::{COLONNES_BOOLEENNES, COLONNES_NUMERIQUES, Sortie};
+use csv::StringRecord;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, params_from_iter};
+use std::error::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const PREFIXE: &str = "sqlite:";
+
+/// Base et lignes à vérifier
+pub struct SourceSqlite {
+    pub chemin: PathBuf,
+    /// Table lue, absente pour une requête
+    pub table: Option<String>,
+    requete: String,
+}
+
+/// Identifiant SQL entre guillemets
+fn identifiant(nom: &str) -> String {
+    format!("\"{}\"", nom.replace('"', "\"\""))
+}
+
+impl SourceSqlite {
+    /// Source désignée par l'argument d'entrée, s'il commence par « sqlite: »
+    pub fn analyser(entree: &str) -> Option<Result<Self, String>> {
+        let reste = entree.strip_prefix(PREFIXE)?;
+        let (chemin, parametre) = match reste.split_once('?') {
+            Some((chemin, parametre)) => (chemin, parametre),
+            None => (reste, ""),
+        };
+        // La requête est prise telle quelle : un « % » de LIKE n'est pas décodé
+        let valeur = |cle: &str| {
+            parametre
+                .strip_prefix(cle)?
+                .strip_prefix('=')
+                .map(str::to_string)
+        };
+        let source = if let Some(table) = valeur("table") {
+            Ok(SourceSqlite {
+                chemin: PathBuf::from(chemin),
+                requete: format!("SELECT * FROM {}", identifiant(&table)),
+                table: Some(table),
+            })
+        } else if let Some(requete) = valeur("query") {
+            Ok(SourceSqlite {
+                chemin: PathBuf::from(chemin),
+                table: None,
+                requete,
+            })
+        } else {
+            Err(format!(
+                "{entree} : « {PREFIXE}chemin?table=… » ou « {PREFIXE}chemin?query=… » attendu"
+            ))
+        };
+        Some(source)
+    }
+
+    /// En-tête (noms des colonnes) et lignes, valeurs converties en texte
+    pub fn lire(&self) -> Result<(StringRecord, Vec<StringRecord>), Box<dyn Error>> {
+        if !self.chemin.exists() {
+            return Err(format!("base introuvable : {}", self.chemin.display()).into());
+        }
+        let connexion = Connection::open(&self.chemin)?;
+        let mut requete = connexion.prepare(&self.requete)?;
+        let entete: StringRecord = requete.column_names().into_iter().collect();
+        let nombre = entete.len();
+        let lignes = requete
+            .query_map([], |ligne| {
+                (0..nombre)
+                    .map(|i| {
+                        Ok(match ligne.get_ref(i)? {
+                            ValueRef::Null => String::new(),
+                            ValueRef::Integer(entier) => entier.to_string(),
+                            ValueRef::Real(reel) => reel.to_string(),
+                            ValueRef::Text(texte) | ValueRef::Blob(texte) => {
+                                String::from_utf8_lossy(texte).into_owned()
+                            }
+                        })
+                    })
+                    .collect::<rusqlite::Result<StringRecord>>()
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((entete, lignes))
+    }
+
+    /// Types déclarés des colonnes de la table lue, dans l'ordre ; vide pour une requête
+    pub fn types(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let Some(table) = &self.table else {
+            return Ok(Vec::new());
+        };
+        let connexion = Connection::open(&self.chemin)?;
+        let types = connexion
+            .prepare("SELECT type FROM pragma_table_info(?) ORDER BY cid")?
+            .query_map([table], |ligne| ligne.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(types)
+    }
+}
+
+/// Valeur SQL d'une colonne écrite : nombre, booléen (0/1) ou texte
+fn valeur(colonne: &str, texte: &str) -> Value {
+    if COLONNES_NUMERIQUES.contains(&colonne) {
+        texte.parse::<f64>().map_or(Value::Null, Value::Real)
+    } else if COLONNES_BOOLEENNES.contains(&colonne) {
+        texte
+            .parse::<bool>()
+            .map_or(Value::Null, |b| Value::Integer(b.into()))
+    } else {
+        Value::Text(texte.to_string())
+    }
+}
+
+/// Type SQL d'une colonne écrite
+fn type_colonne(colonne: &str) -> &'static str {
+    if COLONNES_NUMERIQUES.contains(&colonne) {
+        "REAL"
+    } else if COLONNES_BOOLEENNES.contains(&colonne) {
+        "INTEGER"
+    } else {
+        "TEXT"
+    }
+}
+
+/// Ouvre la base en écriture, dans une transaction validée à chaque point de reprise
+fn ouvrir(chemin: &Path) -> Result<Connection, Box<dyn Error>> {
+    let connexion = Connection::open(chemin)?;
+    connexion.execute_batch("BEGIN")?;
+    Ok(connexion)
+}
+
+/// Rend durable ce qui a été écrit
+fn valider(connexion: &Connection) -> Result<(), Box<dyn Error>> {
+    connexion.execute_batch("COMMIT; BEGIN")?;
+    Ok(())
+}
+
+/// Résultats écrits dans une nouvelle table
+pub struct SortieTable {
+    connexion: Connection,
+    insertion: String,
+    colonnes: Vec<String>,
+}
+
+impl SortieTable {
+    /// Crée la table ; une table existante n'est remplacée que si `remplacer`
+    ///
+    /// Les colonnes recopiées gardent le type déclaré dans la table lue
+    /// (`types_entree`, dans l'ordre de l'entrée), les colonnes de validation
+    /// occupent `validation`.
+    pub fn new(
+        chemin: &Path,
+        table: &str,
+        entete: &StringRecord,
+        validation: Range<usize>,
+        types_entree: &[String],
+        remplacer: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Une table déjà vérifiée contient les colonnes de validation : les doublons sont renommés
+        let mut colonnes: Vec<String> = Vec::new();
+        for nom in entete {
+            let mut unique = nom.to_string();
+            while colonnes.contains(&unique) {
+                unique.push_str("_chk");
+            }
+            colonnes.push(unique);
+        }
+        let definitions: Vec<String> = entete
+            .iter()
+            .zip(&colonnes)
+            .enumerate()
+            .map(|(i, (nom, unique))| {
+                let type_sql = if validation.contains(&i) {
+                    type_colonne(nom)
+                } else {
+                    let j = if i < validation.start {
+                        i
+                    } else {
+                        i - validation.len()
+                    };
+                    types_entree.get(j).map_or("TEXT", String::as_str)
+                };
+                format!("{} {type_sql}", identifiant(unique))
+            })
+            .collect();
+
+        let connexion = ouvrir(chemin)?;
+        let existe: bool = connexion.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ? COLLATE NOCASE)",
+            [table],
+            |ligne| ligne.get(0),
+        )?;
+        if existe && !remplacer {
+            return Err(format!(
+                "la table {table} existe déjà : --replace-table pour la remplacer"
+            )
+            .into());
+        }
+        connexion.execute_batch(&format!(
+            "DROP TABLE IF EXISTS {0}; CREATE TABLE {0} ({1})",
+            identifiant(table),
+            definitions.join(", ")
+        ))?;
+        let insertion = format!(
+            "INSERT INTO {} VALUES ({})",
+            identifiant(table),
+            vec!["?"; colonnes.len()].join(", ")
+        );
+        Ok(SortieTable {
+            connexion,
+            insertion,
+            colonnes: entete.iter().map(str::to_string).collect(),
+        })
+    }
+}
+
+impl Sortie for SortieTable {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        let valeurs = self
+            .colonnes
+            .iter()
+            .zip(ligne.iter())
+            .map(|(colonne, texte)| valeur(colonne, texte));
+        self.connexion
+            .prepare_cached(&self.insertion)?
+            .execute(params_from_iter(valeurs))?;
+        Ok(())
+    }
+
+    /// Les lignes écrites sont validées, mais une table recréée ne peut pas être reprise
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        valider(&self.connexion)?;
+        Ok(None)
+    }
+
+    fn terminer(self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.connexion.execute_batch("COMMIT")?;
+        Ok(None)
+    }
+}
+
+/// Colonnes de validation ajoutées à la table source et renseignées ligne par ligne
+pub struct SortieMiseAJour {
+    connexion: Connection,
+    mise_a_jour: String,
+    /// Position de la clé dans les lignes écrites
+    cle: usize,
+    /// Position des colonnes de validation dans les lignes écrites
+    validation: Range<usize>,
+    colonnes: Vec<String>,
+    lignes_sans_correspondance: u64,
+}
+
+impl SortieMiseAJour {
+    pub fn new(
+        chemin: &Path,
+        table: &str,
+        entete: &StringRecord,
+        cle: usize,
+        validation: Range<usize>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let connexion = ouvrir(chemin)?;
+        let existantes: Vec<String> = connexion
+            .prepare("SELECT name FROM pragma_table_info(?)")?
+            .query_map([table], |ligne| ligne.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let colonnes: Vec<String> = validation.clone().map(|i| entete[i].to_string()).collect();
+        for colonne in &colonnes {
+            if !existantes.contains(colonne) {
+                connexion.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    identifiant(table),
+                    identifiant(colonne),
+                    type_colonne(colonne)
+                ))?;
+            }
+        }
+        let affectations: Vec<String> = colonnes
+            .iter()
+            .map(|colonne| format!("{} = ?", identifiant(colonne)))
+            .collect();
+        let mise_a_jour = format!(
+            "UPDATE {} SET {} WHERE {} = ?",
+            identifiant(table),
+            affectations.join(", "),
+            identifiant(&entete[cle])
+        );
+        Ok(SortieMiseAJour {
+            connexion,
+            mise_a_jour,
+            cle,
+            validation,
+            colonnes,
+            lignes_sans_correspondance: 0,
+        })
+    }
+}
+
+impl Sortie for SortieMiseAJour {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        let mut valeurs: Vec<Value> = self
+            .colonnes
+            .iter()
+            .zip(self.validation.clone())
+            .map(|(colonne, i)| valeur(colonne, &ligne[i]))
+            .collect();
+        // La clé est comparée avec l'affinité de sa colonne : « 12 » retrouve l'entier 12
+        valeurs.push(Value::Text(ligne[self.cle].to_string()));
+        let modifiees = self
+            .connexion
+            .prepare_cached(&self.mise_a_jour)?
+            .execute(params_from_iter(valeurs))?;
+        if modifiees == 0 {
+            self.lignes_sans_correspondance += 1;
+        }
+        Ok(())
+    }
+
+    /// Les mises à jour sont validées ; relancer la vérification refait toutes les lignes
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        valider(&self.connexion)?;
+        Ok(None)
+    }
+
+    fn terminer(self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.connexion.execute_batch("COMMIT")?;
+        Ok((self.lignes_sans_correspondance > 0).then(|| {
+            format!(
+                "{} lignes n'ont été retrouvées par leur clé dans la table source",
+                self.lignes_sans_correspondance
+            )
+        }))
+    }
+}
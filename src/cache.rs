@@ -0,0 +1,288 @@
+//! Cache des réponses des géocodeurs
+//!
+//! Deux niveaux : un cache disque SQLite conservé d'une exécution à l'autre,
+//! et un cache mémoire qui évite d'interroger deux fois le géocodeur pour
+//! une même adresse dans un fichier, y compris entre threads.
+
+use crate::geocodeur::{Correspondance, ErreurGeocodage, Geocodeur, Requete};
+use crate::texte::normaliser;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Fichier du cache disque par défaut
+pub const FICHIER_PAR_DEFAUT: &str = "cache/reponses.sqlite";
+
+fn maintenant() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Réponses enregistrées sur disque, par source et par adresse normalisée
+pub struct CacheDisque {
+    connexion: Mutex<Connection>,
+    duree_de_vie: Duration,
+}
+
+/// Contenu du cache pour `cache stats`
+pub struct Statistiques {
+    pub par_source: Vec<(String, u64, u64)>,
+    pub taille_fichier: u64,
+}
+
+impl CacheDisque {
+    pub fn ouvrir(chemin: &Path, duree_de_vie: Duration) -> Result<Self, Box<dyn Error>> {
+        if let Some(dossier) = chemin.parent() {
+            fs::create_dir_all(dossier)?;
+        }
+        let connexion = Connection::open(chemin)?;
+        connexion.execute_batch(
+            "CREATE TABLE IF NOT EXISTS reponses (
+                source TEXT NOT NULL,
+                cle TEXT NOT NULL,
+                candidats TEXT NOT NULL,
+                enregistre_le INTEGER NOT NULL,
+                PRIMARY KEY (source, cle)
+            )",
+        )?;
+        Ok(CacheDisque {
+            connexion: Mutex::new(connexion),
+            duree_de_vie,
+        })
+    }
+
+    fn limite_fraicheur(&self) -> i64 {
+        maintenant() - self.duree_de_vie.as_secs() as i64
+    }
+
+    /// Réponse encore valide pour cette adresse, s'il y en a une
+    fn lire(&self, source: &str, cle: &str) -> Option<Vec<Correspondance>> {
+        let connexion = self.connexion.lock().unwrap();
+        let candidats: Option<String> = connexion
+            .query_row(
+                "SELECT candidats FROM reponses
+                 WHERE source = ?1 AND cle = ?2 AND enregistre_le >= ?3",
+                params![source, cle, self.limite_fraicheur()],
+                |ligne| ligne.get(0),
+            )
+            .optional()
+            .ok()
+            .flatten();
+        // Une entrée illisible est traitée comme absente et sera réécrite
+        serde_json::from_str(&candidats?).ok()
+    }
+
+    /// Un échec d'écriture ne doit pas faire échouer la vérification
+    fn ecrire(&self, source: &str, cle: &str, candidats: &[Correspondance]) {
+        let Ok(json) = serde_json::to_string(candidats) else {
+            return;
+        };
+        let connexion = self.connexion.lock().unwrap();
+        let _ = connexion.execute(
+            "INSERT OR REPLACE INTO reponses (source, cle, candidats, enregistre_le)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![source, cle, json, maintenant()],
+        );
+    }
+
+    /// Nombre d'entrées (valides, expirées) par source
+    pub fn statistiques(&self, chemin: &Path) -> Result<Statistiques, Box<dyn Error>> {
+        let connexion = self.connexion.lock().unwrap();
+        let mut requete = connexion.prepare(
+            "SELECT source,
+                    SUM(enregistre_le >= ?1),
+                    SUM(enregistre_le < ?1)
+             FROM reponses GROUP BY source ORDER BY source",
+        )?;
+        let par_source = requete
+            .query_map(params![self.limite_fraicheur()], |ligne| {
+                Ok((ligne.get(0)?, ligne.get(1)?, ligne.get(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Statistiques {
+            par_source,
+            taille_fichier: fs::metadata(chemin)?.len(),
+        })
+    }
+
+    /// Supprime les entrées expirées, ou toutes si `tout`, et renvoie leur nombre
+    pub fn purger(&self, tout: bool) -> Result<usize, Box<dyn Error>> {
+        let connexion = self.connexion.lock().unwrap();
+        let supprimees = if tout {
+            connexion.execute("DELETE FROM reponses", [])?
+        } else {
+            connexion.execute(
+                "DELETE FROM reponses WHERE enregistre_le < ?1",
+                params![self.limite_fraicheur()],
+            )?
+        };
+        connexion.execute_batch("VACUUM")?;
+        Ok(supprimees)
+    }
+}
+
+type Reponse = Result<Vec<Correspondance>, ErreurGeocodage>;
+
+/// Candidats d'une adresse, vide tant qu'aucune réponse n'a abouti
+type Cellule = Arc<Mutex<Option<Vec<Correspondance>>>>;
+
+/// Géocodeur interrogé seulement pour les adresses absentes des caches
+pub struct GeocodeurEnCache {
+    interne: Box<dyn Geocodeur>,
+    /// Identifie le géocodeur (backend et adresse du service) dans le cache disque
+    source: String,
+    disque: Option<CacheDisque>,
+    /// Une cellule par adresse : le premier thread la remplit, les suivants
+    /// attendent ; une erreur n'est pas retenue et sera retentée
+    memoire: Mutex<HashMap<String, Cellule>>,
+}
+
+impl GeocodeurEnCache {
+    pub fn new(interne: Box<dyn Geocodeur>, source: String, disque: Option<CacheDisque>) -> Self {
+        GeocodeurEnCache {
+            interne,
+            source,
+            disque,
+            memoire: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Clé de cache : requête normalisée, pour que la casse et la ponctuation
+    /// n'empêchent pas de retrouver une adresse déjà vue
+    fn cle(requete: &Requete, limite: usize) -> String {
+        format!("{limite}|{}", normaliser(&requete.texte()))
+    }
+
+    fn cellule(&self, cle: &str) -> Cellule {
+        let mut memoire = self.memoire.lock().unwrap();
+        Arc::clone(memoire.entry(cle.to_string()).or_default())
+    }
+
+    fn lire_disque(&self, cle: &str) -> Option<Vec<Correspondance>> {
+        self.disque.as_ref()?.lire(&self.source, cle)
+    }
+
+    fn ecrire_disque(&self, cle: &str, candidats: &[Correspondance]) {
+        if let Some(disque) = &self.disque {
+            disque.ecrire(&self.source, cle, candidats);
+        }
+    }
+}
+
+impl Geocodeur for GeocodeurEnCache {
+    fn geocoder(&self, requete: &Requete, limite: usize) -> Reponse {
+        let cle = Self::cle(requete, limite);
+        let cellule = self.cellule(&cle);
+        let mut candidats = cellule.lock().unwrap();
+        if let Some(candidats) = &*candidats {
+            return Ok(candidats.clone());
+        }
+        let reponse = match self.lire_disque(&cle) {
+            Some(trouves) => Ok(trouves),
+            None => {
+                let reponse = self.interne.geocoder(requete, limite);
+                if let Ok(trouves) = &reponse {
+                    self.ecrire_disque(&cle, trouves);
+                }
+                reponse
+            }
+        };
+        if let Ok(trouves) = &reponse {
+            *candidats = Some(trouves.clone());
+        }
+        reponse
+    }
+
+    /// Seules les adresses inconnues des caches sont envoyées au géocodeur, une fois chacune
+    fn geocoder_lot(
+        &self,
+        requetes: &[Requete],
+    ) -> Result<Vec<Option<Correspondance>>, ErreurGeocodage> {
+        let cles: Vec<String> = requetes.iter().map(|r| Self::cle(r, 1)).collect();
+        let mut connues: HashMap<&str, Option<Correspondance>> = HashMap::new();
+        let mut en_attente: HashSet<&str> = HashSet::new();
+        let mut a_chercher: Vec<usize> = Vec::new();
+        for (i, cle) in cles.iter().enumerate() {
+            if connues.contains_key(cle.as_str()) || en_attente.contains(cle.as_str()) {
+                continue;
+            }
+            // Une erreur déjà rencontrée pour cette adresse est retentée
+            let candidats = match self.cellule(cle).lock().unwrap().clone() {
+                Some(candidats) => Some(candidats),
+                None => self.lire_disque(cle),
+            };
+            match candidats {
+                Some(candidats) => {
+                    connues.insert(cle, candidats.into_iter().next());
+                }
+                None => {
+                    en_attente.insert(cle);
+                    a_chercher.push(i);
+                }
+            }
+        }
+
+        if !a_chercher.is_empty() {
+            let lot: Vec<Requete> = a_chercher.iter().map(|&i| requetes[i]).collect();
+            let resultats = self.interne.geocoder_lot(&lot)?;
+            for (&i, resultat) in a_chercher.iter().zip(resultats) {
+                let candidats: Vec<Correspondance> = resultat.iter().cloned().collect();
+                self.ecrire_disque(&cles[i], &candidats);
+                *self.cellule(&cles[i]).lock().unwrap() = Some(candidats);
+                connues.insert(&cles[i], resultat);
+            }
+        }
+
+        Ok(cles
+            .iter()
+            .map(|cle| connues[cle.as_str()].clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Géocodeur qui échoue à son premier appel puis ne trouve rien
+    struct Intermittent {
+        appels: Arc<AtomicU32>,
+    }
+
+    impl Geocodeur for Intermittent {
+        fn geocoder(&self, _: &Requete, _: usize) -> Reponse {
+            match self.appels.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(ErreurGeocodage::Http(503)),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    #[test]
+    fn erreur_retentee() {
+        let appels = Arc::new(AtomicU32::new(0));
+        let geocodeur = GeocodeurEnCache::new(
+            Box::new(Intermittent {
+                appels: Arc::clone(&appels),
+            }),
+            "test".to_string(),
+            None,
+        );
+        let requete = Requete {
+            adresse: "12 rue de la Paix",
+            cp: "75002",
+            ville: "Paris",
+        };
+        assert!(geocodeur.geocoder(&requete, 1).is_err());
+        assert!(geocodeur.geocoder(&requete, 1).is_ok());
+        // La réponse obtenue est ensuite servie par le cache
+        assert!(geocodeur.geocoder(&requete, 1).is_ok());
+        assert_eq!(appels.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,249 @@
+//! Contrôle hors ligne de la cohérence code postal / commune
+//!
+//! La référence est la « base officielle des codes postaux » de La Poste
+//! (fichier CSV publié sur data.gouv.fr), lue depuis un chemin local ; ses
+//! différentes éditions (séparateur, encodage, noms de colonnes) sont acceptées.
+
+use crate::dialecte;
+use crate::encodage;
+use crate::texte::{nom_commune, normaliser};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Commune desservie par un code postal
+struct Commune {
+    insee: String,
+    /// Nom, libellé d'acheminement et ligne 5 (ancienne commune, lieu-dit), sous forme de comparaison
+    noms: Vec<String>,
+}
+
+/// Base des codes postaux chargée en mémoire
+pub struct CodesPostaux {
+    /// Communes desservies par chaque code postal
+    par_cp: HashMap<String, Vec<Commune>>,
+    /// Codes postaux de chaque nom de commune
+    par_nom: HashMap<String, BTreeSet<String>>,
+}
+
+/// Issue du contrôle d'un couple code postal / commune
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EtatCp {
+    /// La commune est desservie par le code postal
+    Coherent,
+    /// Le code postal existe mais ne dessert pas la commune
+    CommuneDifferente,
+    /// Le code postal n'existe pas (code CEDEX ou erroné)
+    CpInconnu,
+    /// Pas de code postal en entrée
+    CpAbsent,
+    /// Pas de commune en entrée : rien à comparer
+    NonControle,
+}
+
+impl EtatCp {
+    pub fn nom(self) -> &'static str {
+        match self {
+            EtatCp::Coherent => "coherent",
+            EtatCp::CommuneDifferente => "commune_differente",
+            EtatCp::CpInconnu => "cp_inconnu",
+            EtatCp::CpAbsent => "cp_absent",
+            EtatCp::NonControle => "non_controle",
+        }
+    }
+}
+
+/// Résultat du contrôle, écrit dans les colonnes cp_controle, cp_suggere et insee_commune
+#[derive(Debug, Clone)]
+pub struct ControleCp {
+    pub etat: EtatCp,
+    /// Codes postaux de la commune quand celui de l'entrée ne lui correspond pas
+    pub suggestion: Vec<String>,
+    /// Code INSEE de la commune reconnue
+    pub insee: Option<String>,
+}
+
+/// Colonnes de la base, repérées d'après leur en-tête normalisé
+fn position(entete: &[String], noms: &[&str]) -> Option<usize> {
+    entete
+        .iter()
+        .position(|titre| noms.contains(&titre.as_str()))
+}
+
+impl CodesPostaux {
+    pub fn charger(chemin: &Path) -> Result<Self, Box<dyn Error>> {
+        let octets = fs::read(chemin)
+            .map_err(|e| format!("base des codes postaux {} : {e}", chemin.display()))?;
+        let texte = encodage::decoder(&octets, None).texte;
+        let forme = dialecte::Dialecte {
+            separateur: dialecte::detecter_separateur(&texte, b'"'),
+            guillemet: b'"',
+            echappement: dialecte::Echappement::Double,
+            crlf: false,
+        };
+        let mut lecteur = forme.lecteur().from_reader(texte.as_bytes());
+        let entete: Vec<String> = lecteur.headers()?.iter().map(normaliser).collect();
+        let colonne = |noms: &[&str]| {
+            position(&entete, noms).ok_or_else(|| {
+                format!(
+                    "{} : colonne {} introuvable, base officielle des codes postaux attendue",
+                    chemin.display(),
+                    noms[0]
+                )
+            })
+        };
+        let insee = colonne(&["CODE COMMUNE INSEE", "CODE INSEE"])?;
+        let nom = colonne(&["NOM DE LA COMMUNE", "NOM COMMUNE"])?;
+        let cp = colonne(&["CODE POSTAL"])?;
+        let acheminement = position(&entete, &["LIBELLE D ACHEMINEMENT", "LIBELLE ACHEMINEMENT"]);
+        let ligne_5 = position(&entete, &["LIGNE 5"]);
+
+        let mut base = CodesPostaux {
+            par_cp: HashMap::new(),
+            par_nom: HashMap::new(),
+        };
+        for ligne in lecteur.records() {
+            let ligne = ligne?;
+            let valeur = |position: Option<usize>| {
+                position
+                    .and_then(|position| ligne.get(position))
+                    .unwrap_or_default()
+                    .trim()
+            };
+            let code = valeur(Some(cp));
+            if code.is_empty() {
+                continue;
+            }
+            // Les codes perdent leur zéro initial quand le fichier passe par un tableur
+            let code = format!("{code:0>5}");
+            let noms: Vec<String> = [valeur(Some(nom)), valeur(acheminement), valeur(ligne_5)]
+                .into_iter()
+                .filter(|nom| !nom.is_empty())
+                .map(nom_commune)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            for nom in &noms {
+                base.par_nom
+                    .entry(nom.clone())
+                    .or_default()
+                    .insert(code.clone());
+            }
+            base.par_cp.entry(code).or_default().push(Commune {
+                insee: format!("{:0>5}", valeur(Some(insee))),
+                noms,
+            });
+        }
+        if base.par_cp.is_empty() {
+            return Err(format!("{} : aucun code postal lu", chemin.display()).into());
+        }
+        Ok(base)
+    }
+
+    /// Vérifie que la commune est desservie par le code postal, sans géocodeur
+    pub fn controler(&self, cp: &str, ville: &str) -> ControleCp {
+        let cp = cp.trim();
+        let ville = nom_commune(ville);
+        let suggestion = || {
+            self.par_nom
+                .get(&ville)
+                .map(|codes| codes.iter().cloned().collect())
+                .unwrap_or_default()
+        };
+        let (etat, insee) = if ville.is_empty() {
+            (EtatCp::NonControle, None)
+        } else if cp.is_empty() {
+            (EtatCp::CpAbsent, None)
+        } else {
+            match self.par_cp.get(cp) {
+                None => (EtatCp::CpInconnu, None),
+                Some(communes) => match communes.iter().find(|c| c.noms.contains(&ville)) {
+                    Some(commune) => (EtatCp::Coherent, Some(commune.insee.clone())),
+                    None => (EtatCp::CommuneDifferente, None),
+                },
+            }
+        };
+        ControleCp {
+            etat,
+            suggestion: match etat {
+                EtatCp::CommuneDifferente | EtatCp::CpInconnu | EtatCp::CpAbsent => suggestion(),
+                EtatCp::Coherent | EtatCp::NonControle => Vec::new(),
+            },
+            insee,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base chargée depuis un fichier temporaire
+    fn charger(nom: &str, contenu: &str) -> Result<CodesPostaux, Box<dyn Error>> {
+        let chemin = std::env::temp_dir().join(format!("{nom}_{}.csv", std::process::id()));
+        fs::write(&chemin, contenu).unwrap();
+        let base = CodesPostaux::charger(&chemin);
+        fs::remove_file(&chemin).unwrap();
+        base
+    }
+
+    /// Édition historique : « ; », colonnes Nom_commune et Ligne_5, zéros perdus
+    fn edition_historique() -> CodesPostaux {
+        charger(
+            "laposte_hexasmal",
+            "#Code_commune_INSEE;Nom_commune;Code_postal;Ligne_5;Libellé_d_acheminement\n\
+             75116;PARIS 16;75016;;PARIS\n\
+             75116;PARIS 16;75116;;PARIS\n\
+             59350;LILLE;59000;;LILLE\n\
+             59350;LILLE;59800;;LILLE\n\
+             1001;L ABERGEMENT CLEMENCIAT;1400;;L ABERGEMENT CLEMENCIAT\n\
+             62765;ST OMER;62500;;ST OMER\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn communes_reconnues() {
+        let base = edition_historique();
+        let controle = base.controler("75016", "Paris 16e Arrondissement");
+        assert_eq!(controle.etat, EtatCp::Coherent);
+        assert_eq!(controle.insee.as_deref(), Some("75116"));
+        let controle = base.controler("01400", "L'Abergement-Clémenciat");
+        assert_eq!(controle.etat, EtatCp::Coherent);
+        assert_eq!(controle.insee.as_deref(), Some("01001"));
+        assert_eq!(base.controler("62500", "Saint-Omer").etat, EtatCp::Coherent);
+    }
+
+    #[test]
+    fn suggestions() {
+        let base = edition_historique();
+        let controle = base.controler("75016", "Lille");
+        assert_eq!(controle.etat, EtatCp::CommuneDifferente);
+        assert_eq!(controle.suggestion, ["59000", "59800"]);
+        let controle = base.controler("59999", "Lille");
+        assert_eq!(controle.etat, EtatCp::CpInconnu);
+        assert_eq!(controle.suggestion, ["59000", "59800"]);
+        assert_eq!(base.controler("", "Lille").etat, EtatCp::CpAbsent);
+        let controle = base.controler("59000", "");
+        assert_eq!(controle.etat, EtatCp::NonControle);
+        assert!(controle.suggestion.is_empty());
+    }
+
+    #[test]
+    fn editions_reconnues() {
+        let base = charger(
+            "base_officielle",
+            "code_commune_insee,nom_de_la_commune,code_postal,libelle_d_acheminement,ligne_5\n\
+             59328,LAMBERSART,59130,LAMBERSART,\n\
+             59350,LILLE,59000,LILLE,LOMME\n",
+        )
+        .unwrap();
+        assert_eq!(base.controler("59130", "Lambersart").etat, EtatCp::Coherent);
+        assert_eq!(base.controler("59000", "Lomme").etat, EtatCp::Coherent);
+        let erreur = charger("sans_cp", "code_commune_insee;nom_commune\n59350;LILLE\n")
+            .err()
+            .unwrap();
+        assert!(erreur.to_string().contains("CODE POSTAL"));
+    }
+}
@@ -0,0 +1,319 @@
+//! Repérage des colonnes du fichier d'entrée
+//!
+//! Les colonnes sont reconnues d'après leur en-tête grâce à une table de
+//! synonymes (« Raison sociale », « Adresse 1 », « Code postal »…) ; `--column`
+//! ou un fichier de correspondance désignent celles que la table ne connaît pas.
+
+use crate::texte::normaliser;
+use csv::StringRecord;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Information attendue dans le fichier d'entrée
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Champ {
+    Nom,
+    Adresse,
+    Cp,
+    Ville,
+    Contact,
+    /// Lignes d'adresse supplémentaires (complément, lieu-dit, boîte postale…)
+    Adresse2,
+    Adresse3,
+}
+
+impl Champ {
+    pub const TOUS: [Champ; 7] = [
+        Champ::Nom,
+        Champ::Adresse,
+        Champ::Cp,
+        Champ::Ville,
+        Champ::Contact,
+        Champ::Adresse2,
+        Champ::Adresse3,
+    ];
+
+    /// Ordre des colonnes d'un fichier sans en-tête
+    const SANS_ENTETE: [Champ; 5] = [
+        Champ::Nom,
+        Champ::Adresse,
+        Champ::Cp,
+        Champ::Ville,
+        Champ::Contact,
+    ];
+
+    pub fn nom(self) -> &'static str {
+        match self {
+            Champ::Nom => "nom",
+            Champ::Adresse => "adresse",
+            Champ::Cp => "cp",
+            Champ::Ville => "ville",
+            Champ::Contact => "contact",
+            Champ::Adresse2 => "adresse2",
+            Champ::Adresse3 => "adresse3",
+        }
+    }
+
+    /// Sans adresse, code postal et ville, il n'y a rien à vérifier ; une
+    /// adresse complète contient elle-même le code postal et la ville
+    fn obligatoire(self, adresse_complete: bool) -> bool {
+        match self {
+            Champ::Adresse => true,
+            Champ::Cp | Champ::Ville => !adresse_complete,
+            _ => false,
+        }
+    }
+
+    /// En-têtes reconnus, sous forme normalisée
+    fn synonymes(self) -> &'static [&'static str] {
+        match self {
+            Champ::Nom => &[
+                "NOM",
+                "RAISON SOCIALE",
+                "SOCIETE",
+                "ENTREPRISE",
+                "DENOMINATION",
+                "ETABLISSEMENT",
+                "CLIENT",
+                "NOM CLIENT",
+                "NAME",
+                "COMPANY",
+            ],
+            Champ::Adresse => &[
+                "ADRESSE",
+                "ADRESSE 1",
+                "ADRESSE1",
+                "ADRESSE LIGNE 1",
+                "LIGNE ADRESSE",
+                "ADR",
+                "ADR1",
+                "RUE",
+                "VOIE",
+                "ADDRESS",
+                "ADDRESS 1",
+                "ADDRESS1",
+                "STREET",
+                "ADRESSE COMPLETE",
+                "ADRESSE POSTALE",
+                "FULL ADDRESS",
+            ],
+            Champ::Cp => &[
+                "CP",
+                "CODE POSTAL",
+                "CODEPOSTAL",
+                "CODE POSTALE",
+                "POSTAL CODE",
+                "POSTCODE",
+                "ZIP",
+                "ZIPCODE",
+                "ZIP CODE",
+            ],
+            Champ::Ville => &[
+                "VILLE",
+                "COMMUNE",
+                "NOM COMMUNE",
+                "LIBELLE COMMUNE",
+                "LOCALITE",
+                "CITY",
+                "TOWN",
+            ],
+            Champ::Contact => &[
+                "CONTACT",
+                "INTERLOCUTEUR",
+                "TELEPHONE",
+                "TEL",
+                "EMAIL",
+                "E MAIL",
+                "MAIL",
+            ],
+            Champ::Adresse2 => &[
+                "ADRESSE 2",
+                "ADRESSE2",
+                "ADRESSE LIGNE 2",
+                "ADR2",
+                "COMPLEMENT",
+                "COMPLEMENT D ADRESSE",
+                "ADDRESS 2",
+                "ADDRESS2",
+            ],
+            Champ::Adresse3 => &[
+                "ADRESSE 3",
+                "ADRESSE3",
+                "ADRESSE LIGNE 3",
+                "ADR3",
+                "LIEU DIT",
+                "LIEUDIT",
+                "ADDRESS 3",
+                "ADDRESS3",
+            ],
+        }
+    }
+
+    fn par_nom(nom: &str) -> Option<Champ> {
+        Champ::TOUS
+            .into_iter()
+            .find(|champ| champ.nom() == nom.trim().to_lowercase())
+    }
+}
+
+/// Colonne désignée par l'utilisateur pour un champ : « adresse=Adresse 1 »
+#[derive(Clone)]
+pub struct Designation {
+    champ: Champ,
+    colonne: String,
+}
+
+/// Lit une désignation « champ=colonne » (`--column` et fichier de correspondance)
+pub fn designation(valeur: &str) -> Result<Designation, String> {
+    let (champ, colonne) = valeur
+        .split_once('=')
+        .ok_or_else(|| format!("« {valeur} » : champ=colonne attendu"))?;
+    let champ = Champ::par_nom(champ).ok_or_else(|| {
+        format!(
+            "« {champ} » : champ inconnu (nom, adresse, adresse2, adresse3, cp, ville ou contact)"
+        )
+    })?;
+    Ok(Designation {
+        champ,
+        colonne: colonne.trim().to_string(),
+    })
+}
+
+/// Désignations d'un fichier de correspondance : une ligne « champ=colonne »
+/// par champ, les lignes vides et commençant par # étant ignorées
+pub fn lire_fichier(chemin: &Path) -> Result<Vec<Designation>, Box<dyn Error>> {
+    fs::read_to_string(chemin)?
+        .lines()
+        .map(str::trim)
+        .filter(|ligne| !ligne.is_empty() && !ligne.starts_with('#'))
+        .map(|ligne| {
+            designation(ligne).map_err(|erreur| format!("{} : {erreur}", chemin.display()).into())
+        })
+        .collect()
+}
+
+/// Position d'une colonne désignée par son nom (sans tenir compte de la
+/// casse ni des accents), ou à défaut par son numéro à partir de 1
+fn trouver(noms: &[String], nombre_colonnes: usize, colonne: &str) -> Option<usize> {
+    let nom = normaliser(colonne);
+    noms.iter().position(|n| *n == nom).or_else(|| {
+        colonne
+            .parse::<usize>()
+            .ok()
+            .filter(|&numero| (1..=nombre_colonnes).contains(&numero))
+            .map(|numero| numero - 1)
+    })
+}
+
+/// Position d'insertion des colonnes de validation : après la colonne
+/// désignée (0 pour les placer en tête), ou après la dernière
+pub fn position_insertion(
+    entete: Option<&StringRecord>,
+    nombre_colonnes: usize,
+    apres: Option<&str>,
+) -> Result<usize, String> {
+    let Some(apres) = apres else {
+        return Ok(nombre_colonnes);
+    };
+    if apres.trim() == "0" {
+        return Ok(0);
+    }
+    let noms: Vec<String> = entete
+        .map(|entete| entete.iter().map(normaliser).collect())
+        .unwrap_or_default();
+    trouver(&noms, nombre_colonnes, apres)
+        .map(|position| position + 1)
+        .ok_or_else(|| format!("colonne « {apres} » introuvable pour --insert-after"))
+}
+
+/// Position de chaque champ dans les lignes du fichier
+pub struct Colonnes {
+    positions: [Option<usize>; Champ::TOUS.len()],
+}
+
+impl Colonnes {
+    /// Repère les colonnes d'après l'en-tête, ou d'après leur position si le
+    /// fichier n'en a pas
+    pub fn reperer(
+        entete: Option<&StringRecord>,
+        nombre_colonnes: usize,
+        designations: &[Designation],
+        adresse_complete: bool,
+    ) -> Result<Self, String> {
+        let noms: Vec<String> = entete
+            .map(|entete| entete.iter().map(normaliser).collect())
+            .unwrap_or_default();
+        let mut positions = [None; Champ::TOUS.len()];
+        for (i, champ) in Champ::TOUS.into_iter().enumerate() {
+            // La dernière désignation d'un champ l'emporte
+            let designation = designations.iter().rev().find(|d| d.champ == champ);
+            positions[i] = match designation {
+                Some(designation) => {
+                    let position = trouver(&noms, nombre_colonnes, &designation.colonne);
+                    Some(position.ok_or_else(|| {
+                        format!(
+                            "colonne « {} » introuvable pour {}",
+                            designation.colonne,
+                            champ.nom()
+                        )
+                    })?)
+                }
+                // Sans en-tête ni désignation, les colonnes sont dans l'ordre de SANS_ENTETE
+                None if entete.is_none() && designations.is_empty() => Champ::SANS_ENTETE
+                    .iter()
+                    .position(|c| *c == champ)
+                    .filter(|&i| i < nombre_colonnes),
+                None => noms
+                    .iter()
+                    .position(|nom| champ.synonymes().contains(&nom.as_str())),
+            };
+        }
+
+        if let Some(manquant) =
+            Champ::TOUS
+                .into_iter()
+                .zip(positions)
+                .find_map(|(champ, position)| {
+                    (champ.obligatoire(adresse_complete) && position.is_none()).then_some(champ)
+                })
+        {
+            let colonnes = entete
+                .map(|entete| entete.iter().collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            let alternative = match manquant {
+                Champ::Cp | Champ::Ville => {
+                    ", ou --full-address si l'adresse tient dans une colonne"
+                }
+                _ => "",
+            };
+            return Err(format!(
+                "aucune colonne reconnue pour {0} : utiliser --column {0}=<colonne>{alternative} (colonnes : {colonnes})",
+                manquant.nom()
+            ));
+        }
+        Ok(Colonnes { positions })
+    }
+
+    /// Valeur d'un champ dans une ligne, vide si la colonne est absente
+    pub fn valeur(&self, ligne: &StringRecord, champ: Champ) -> String {
+        self.positions[champ as usize]
+            .and_then(|position| ligne.get(position))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Description des colonnes retenues dont l'en-tête diffère du nom du champ
+    pub fn resume(&self, entete: &StringRecord) -> Option<String> {
+        let renommees: Vec<String> = Champ::TOUS
+            .into_iter()
+            .zip(self.positions)
+            .filter_map(|(champ, position)| {
+                let colonne = entete.get(position?)?;
+                (normaliser(colonne) != normaliser(champ.nom()))
+                    .then(|| format!("{} ← « {colonne} »", champ.nom()))
+            })
+            .collect();
+        (!renommees.is_empty()).then(|| renommees.join(", "))
+    }
+}
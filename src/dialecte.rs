@@ -0,0 +1,145 @@
+//! Dialecte CSV des fichiers d'entrée et de sortie : séparateur, guillemets, fins de ligne
+
+use csv::{ReaderBuilder, Terminator, WriterBuilder};
+
+/// Séparateurs reconnus par la détection automatique, par ordre de préférence
+const SEPARATEURS: [u8; 4] = [b'\t', b';', b',', b'|'];
+
+/// Lignes examinées pour détecter le séparateur
+const LIGNES_ECHANTILLON: usize = 20;
+
+/// Échappement d'un guillemet à l'intérieur d'un champ
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Echappement {
+    /// Guillemet doublé : "" (Excel, RFC 4180)
+    Double,
+    /// Barre oblique inverse : \"
+    Backslash,
+}
+
+/// Caractère d'option : « tab » ou un caractère ASCII
+pub fn caractere(valeur: &str) -> Result<u8, String> {
+    match valeur {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if valeur.len() == 1 && valeur.is_ascii() => Ok(valeur.as_bytes()[0]),
+        _ => Err(format!(
+            "« {valeur} » : un caractère ASCII ou « tab » est attendu"
+        )),
+    }
+}
+
+/// Séparateur le plus probable d'un texte CSV
+///
+/// Retenu : le séparateur présent le même nombre de fois hors guillemets sur
+/// chacune des premières lignes, et à défaut le plus fréquent ; la tabulation
+/// si aucun n'apparaît.
+pub fn detecter_separateur(texte: &str, guillemet: u8) -> u8 {
+    let lignes: Vec<&str> = texte
+        .lines()
+        .filter(|ligne| !ligne.trim().is_empty())
+        .take(LIGNES_ECHANTILLON)
+        .collect();
+    let comptes = |separateur: u8| -> Vec<usize> {
+        lignes
+            .iter()
+            .map(|ligne| {
+                let mut entre_guillemets = false;
+                let mut compte = 0;
+                for &octet in ligne.as_bytes() {
+                    if octet == guillemet {
+                        entre_guillemets = !entre_guillemets;
+                    } else if octet == separateur && !entre_guillemets {
+                        compte += 1;
+                    }
+                }
+                compte
+            })
+            .collect()
+    };
+
+    SEPARATEURS
+        .into_iter()
+        .map(|separateur| {
+            let comptes = comptes(separateur);
+            let minimum = comptes.iter().copied().min().unwrap_or(0);
+            let regulier = minimum > 0 && comptes.iter().all(|&c| c == minimum);
+            let total: usize = comptes.iter().sum();
+            (separateur, (regulier, total))
+        })
+        .filter(|(_, (_, total))| *total > 0)
+        // max_by_key garde le dernier ex aequo : on parcourt à l'envers pour garder l'ordre de préférence
+        .rev()
+        .max_by_key(|(_, critere)| *critere)
+        .map_or(b'\t', |(separateur, _)| separateur)
+}
+
+/// Vrai si les lignes du texte se terminent par CRLF
+pub fn fins_de_ligne_crlf(texte: &str) -> bool {
+    texte
+        .find('\n')
+        .is_some_and(|position| texte[..position].ends_with('\r'))
+}
+
+/// Forme d'un fichier CSV
+#[derive(Clone, Copy)]
+pub struct Dialecte {
+    pub separateur: u8,
+    pub guillemet: u8,
+    pub echappement: Echappement,
+    pub crlf: bool,
+}
+
+impl Dialecte {
+    pub fn lecteur(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder.delimiter(self.separateur).quote(self.guillemet);
+        if self.echappement == Echappement::Backslash {
+            builder.double_quote(false).escape(Some(b'\\'));
+        }
+        builder
+    }
+
+    pub fn ecrivain(&self) -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        builder.delimiter(self.separateur).quote(self.guillemet);
+        if self.echappement == Echappement::Backslash {
+            builder.double_quote(false).escape(b'\\');
+        }
+        if self.crlf {
+            builder.terminator(Terminator::CRLF);
+        }
+        builder
+    }
+}
+
+/// Nom lisible d'un séparateur pour les messages
+pub fn nom_separateur(separateur: u8) -> String {
+    match separateur {
+        b'\t' => "tabulation".to_string(),
+        _ => format!("« {} »", separateur as char),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separateur_entre_guillemets_ignore() {
+        // Plus de virgules que de points-virgules, mais toutes entre guillemets
+        let texte = "nom;adresse;cp\n\
+                     \"Dupont, Martin et fils\";\"12, rue de la Paix, bât. A\";75002\n\
+                     \"SARL X, Y\";\"3, av. Foch\";59000\n";
+        assert_eq!(detecter_separateur(texte, b'"'), b';');
+        let texte = "nom,adresse\n'A; B',12 rue de la Paix\n'C; D',3 av. Foch\n";
+        assert_eq!(detecter_separateur(texte, b'\''), b',');
+    }
+
+    #[test]
+    fn separateur_regulier_prefere() {
+        // Le « | » est plus fréquent mais irrégulier, la tabulation figure sur chaque ligne
+        let texte = "nom\tadresse\nA\t1|2|3|4\nB\t5 rue X\n";
+        assert_eq!(detecter_separateur(texte, b'"'), b'\t');
+        assert_eq!(detecter_separateur("une seule colonne\nA\n", b'"'), b'\t');
+    }
+}
@@ -0,0 +1,289 @@
+//! Détection et conversion des encodages de fichier
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+/// Encodage désigné par son nom (« windows-1252 », « latin1 », « utf-16le »…)
+pub fn par_nom(nom: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(nom.trim().as_bytes()).ok_or_else(|| format!("encodage inconnu : {nom}"))
+}
+
+/// Encodage de sortie désigné par son nom
+///
+/// encoding_rs n'écrit pas l'UTF-16 : seuls les encodages ASCII-compatibles sont acceptés.
+pub fn sortie_par_nom(nom: &str) -> Result<&'static Encoding, String> {
+    let encodage = par_nom(nom)?;
+    if encodage.output_encoding() != encodage {
+        return Err(format!("encodage de sortie non pris en charge : {nom}"));
+    }
+    Ok(encodage)
+}
+
+/// Devine l'encodage d'un fichier texte
+///
+/// Dans l'ordre : BOM, UTF-16 sans BOM (un octet nul sur deux), UTF-8 valide,
+/// et à défaut Windows-1252, qui couvre aussi l'ISO-8859-1 des exports ERP.
+pub fn detecter(octets: &[u8]) -> &'static Encoding {
+    if let Some((encodage, _)) = Encoding::for_bom(octets) {
+        return encodage;
+    }
+
+    // Un texte UTF-16 en alphabet latin a un octet nul par caractère
+    let echantillon = &octets[..octets.len().min(4096) & !1];
+    if !echantillon.is_empty() {
+        let paires = echantillon.len() / 2;
+        let nuls_pairs = echantillon.iter().step_by(2).filter(|&&b| b == 0).count();
+        let nuls_impairs = echantillon
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count();
+        if nuls_impairs * 10 >= paires * 9 && nuls_pairs == 0 {
+            return UTF_16LE;
+        }
+        if nuls_pairs * 10 >= paires * 9 && nuls_impairs == 0 {
+            return UTF_16BE;
+        }
+    }
+
+    if std::str::from_utf8(octets).is_ok() {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// Texte décodé et encodage utilisé
+pub struct Decode {
+    pub texte: String,
+    pub encodage: &'static Encoding,
+    /// Des séquences invalides ont été remplacées par U+FFFD
+    pub avec_erreurs: bool,
+    /// Le fichier commençait par un BOM
+    pub bom: bool,
+}
+
+/// Décode un fichier, avec l'encodage imposé ou à défaut celui détecté
+///
+/// Un BOM éventuel est retiré et prime sur l'encodage imposé, comme le
+/// ferait un éditeur de texte.
+pub fn decoder(octets: &[u8], impose: Option<&'static Encoding>) -> Decode {
+    let encodage = impose.unwrap_or_else(|| detecter(octets));
+    let (texte, encodage, avec_erreurs) = encodage.decode(octets);
+    Decode {
+        texte: texte.into_owned(),
+        encodage,
+        avec_erreurs,
+        bom: Encoding::for_bom(octets).is_some(),
+    }
+}
+
+/// Traitement des caractères absents de l'encodage de sortie
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Politique {
+    /// Arrêter la vérification
+    Error,
+    /// Remplacer le caractère par « ? »
+    Replace,
+    /// Remplacer par un équivalent approché (« ő » → « o », « — » → « - »), sinon « ? »
+    Transliterate,
+}
+
+/// Équivalent ASCII des signes typographiques courants absents des encodages latins
+fn translitterer_signe(c: char) -> Option<&'static str> {
+    let equivalent = match c {
+        '‘' | '’' | '‚' | '′' => "'",
+        '“' | '”' | '„' | '″' => "\"",
+        '–' | '—' | '‐' | '‑' | '−' => "-",
+        '…' => "...",
+        '\u{a0}' | '\u{202f}' | '\u{2009}' => " ",
+        '€' => "EUR",
+        _ => return None,
+    };
+    Some(equivalent)
+}
+
+/// Écrit dans l'encodage de sortie le texte UTF-8 produit par le writer CSV
+///
+/// Les caractères non représentables sont comptés : l'appelant compare le
+/// compteur avant et après chaque ligne pour savoir quelles lignes sont touchées.
+pub struct Transcodeur<W> {
+    interne: W,
+    /// Absent pour une sortie UTF-8, écrite telle quelle
+    encodeur: Option<encoding_rs::Encoder>,
+    politique: Politique,
+    /// Fin de tampon coupée au milieu d'un caractère UTF-8
+    en_attente: Vec<u8>,
+    non_representables: u64,
+}
+
+impl<W: std::io::Write> Transcodeur<W> {
+    pub fn new(interne: W, encodage: &'static Encoding, politique: Politique) -> Self {
+        Transcodeur {
+            interne,
+            encodeur: (encodage != UTF_8).then(|| encodage.new_encoder()),
+            politique,
+            en_attente: Vec::new(),
+            non_representables: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.interne
+    }
+
+    /// Nombre total de caractères non représentables rencontrés
+    pub fn non_representables(&self) -> u64 {
+        self.non_representables
+    }
+
+    fn encoder(&mut self, texte: &str) -> std::io::Result<()> {
+        let Some(encodeur) = self.encodeur.as_mut() else {
+            return self.interne.write_all(texte.as_bytes());
+        };
+        let mut sortie = Vec::with_capacity(texte.len() + 16);
+        let mut reste = texte;
+        loop {
+            let (resultat, lus) =
+                encodeur.encode_from_utf8_to_vec_without_replacement(reste, &mut sortie, false);
+            reste = &reste[lus..];
+            match resultat {
+                encoding_rs::EncoderResult::InputEmpty => break,
+                encoding_rs::EncoderResult::OutputFull => sortie.reserve(reste.len() + 16),
+                encoding_rs::EncoderResult::Unmappable(c) => {
+                    self.non_representables += 1;
+                    let encodage = encodeur.encoding();
+                    let remplacement = match self.politique {
+                        Politique::Error => {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                format!(
+                                    "caractère « {c} » non représentable en {}",
+                                    encodage.name()
+                                ),
+                            ));
+                        }
+                        Politique::Replace => "?".to_string(),
+                        Politique::Transliterate => translitterer_signe(c)
+                            .map(str::to_string)
+                            .unwrap_or_else(|| crate::texte::sans_accents(&c.to_string())),
+                    };
+                    let (encode, _, erreurs) = encodage.encode(&remplacement);
+                    if erreurs {
+                        sortie.push(b'?');
+                    } else {
+                        sortie.extend_from_slice(&encode);
+                    }
+                }
+            }
+        }
+        self.interne.write_all(&sortie)
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for Transcodeur<W> {
+    fn write(&mut self, octets: &[u8]) -> std::io::Result<usize> {
+        self.en_attente.extend_from_slice(octets);
+        let valides = match std::str::from_utf8(&self.en_attente) {
+            Ok(texte) => texte.len(),
+            // Caractère incomplet en fin de tampon : il sera complété au prochain appel
+            Err(erreur) if erreur.error_len().is_none() => erreur.valid_up_to(),
+            Err(erreur) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, erreur));
+            }
+        };
+        let tampon = std::mem::take(&mut self.en_attente);
+        let (texte, reste) = tampon.split_at(valides);
+        self.en_attente = reste.to_vec();
+        // Validé ci-dessus
+        self.encoder(std::str::from_utf8(texte).unwrap())?;
+        Ok(octets.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.interne.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Texte encodé en UTF-16 sans BOM
+    fn utf16(texte: &str, gros_boutiste: bool) -> Vec<u8> {
+        texte
+            .encode_utf16()
+            .flat_map(|unite| {
+                if gros_boutiste {
+                    unite.to_be_bytes()
+                } else {
+                    unite.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodage_detecte() {
+        let texte = "nom\tville\nDupont\tSaint-Étienne\n";
+        assert_eq!(detecter(&utf16(texte, false)), UTF_16LE);
+        assert_eq!(detecter(&utf16(texte, true)), UTF_16BE);
+        let (ansi, _, _) = WINDOWS_1252.encode(texte);
+        assert_eq!(detecter(&ansi), WINDOWS_1252);
+        assert_eq!(detecter(texte.as_bytes()), UTF_8);
+        assert_eq!(detecter(b"\xEF\xBB\xBFnom\xE9"), UTF_8);
+        assert_eq!(detecter(b"\xFF\xFEn\0o\0m\0"), UTF_16LE);
+    }
+
+    #[test]
+    fn windows_1252_non_pris_pour_utf16() {
+        // Quelques octets nuls isolés ne suffisent pas à faire de l'UTF-16
+        let octets = b"nom;ville\0\nDupont;Saint-\xC9tienne\n";
+        assert_eq!(detecter(octets), WINDOWS_1252);
+        let decode = decoder(&utf16("cp\tville\n59000\tLille\n", false), None);
+        assert_eq!(decode.texte, "cp\tville\n59000\tLille\n");
+        assert!(!decode.bom);
+    }
+
+    /// Texte écrit en plusieurs morceaux, tel que reçu par la sortie
+    fn transcoder(
+        morceaux: &[&[u8]],
+        encodage: &'static Encoding,
+        politique: Politique,
+    ) -> (std::io::Result<Vec<u8>>, u64) {
+        let mut transcodeur = Transcodeur::new(Vec::new(), encodage, politique);
+        let resultat = morceaux
+            .iter()
+            .try_for_each(|morceau| transcodeur.write_all(morceau))
+            .map(|_| transcodeur.get_ref().clone());
+        (resultat, transcodeur.non_representables())
+    }
+
+    #[test]
+    fn caractere_coupe_entre_deux_ecritures() {
+        // « é » (C3 A9) arrive en deux appels
+        let (sortie, _) = transcoder(
+            &[b"Saint-\xC3", b"\xA9tienne\n"],
+            WINDOWS_1252,
+            Politique::Error,
+        );
+        assert_eq!(sortie.unwrap(), b"Saint-\xE9tienne\n");
+        let (sortie, _) = transcoder(&[b"\xE2\x82", b"\xAC"], UTF_8, Politique::Error);
+        assert_eq!(sortie.unwrap(), "€".as_bytes());
+    }
+
+    #[test]
+    fn politiques_non_representables() {
+        let texte = "Őrs\u{2011}Zoë".as_bytes();
+        let (sortie, compte) = transcoder(&[texte], WINDOWS_1252, Politique::Error);
+        assert!(sortie.is_err());
+        assert_eq!(compte, 1);
+        let (sortie, compte) = transcoder(&[texte], WINDOWS_1252, Politique::Replace);
+        assert_eq!(sortie.unwrap(), b"?rs?Zo\xEB");
+        assert_eq!(compte, 2);
+        let (sortie, compte) = transcoder(&[texte], WINDOWS_1252, Politique::Transliterate);
+        assert_eq!(sortie.unwrap(), b"Ors-Zo\xEB");
+        assert_eq!(compte, 2);
+    }
+}
@@ -0,0 +1,181 @@
+//! Interface commune aux sources de vérification (API BAN, Addok, Nominatim, Photon, index local)
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Adresse à vérifier, telle que lue dans le fichier
+#[derive(Clone, Copy)]
+pub struct Requete<'a> {
+    pub adresse: &'a str,
+    pub cp: &'a str,
+    pub ville: &'a str,
+}
+
+impl Requete<'_> {
+    /// Texte libre envoyé aux géocodeurs : « adresse, cp ville »
+    pub fn texte(&self) -> String {
+        format!("{}, {} {}", self.adresse, self.cp, self.ville)
+    }
+}
+
+/// Candidat renvoyé par un géocodeur pour une adresse
+///
+/// Se lit directement depuis les `properties` d'une réponse BAN ; les
+/// coordonnées viennent de la géométrie et sont complétées ensuite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Correspondance {
+    pub label: String,
+    /// Entre 0 et 1, comparable d'un géocodeur à l'autre
+    pub score: f64,
+    /// housenumber, street, locality ou municipality
+    #[serde(rename = "type")]
+    pub type_resultat: String,
+    pub id: String,
+    /// Code INSEE, vide si la source ne le fournit pas
+    pub citycode: String,
+    pub postcode: Option<String>,
+    pub city: String,
+    /// Numéro (indice de répétition compris) et voie trouvés, s'ils sont connus
+    #[serde(default)]
+    pub housenumber: Option<String>,
+    #[serde(default)]
+    pub street: Option<String>,
+    #[serde(default)]
+    pub longitude: f64,
+    #[serde(default)]
+    pub latitude: f64,
+}
+
+/// Précision d'un résultat, de la moins fine à la plus fine
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, clap::ValueEnum)]
+pub enum Granularite {
+    Municipality,
+    Locality,
+    Street,
+    Housenumber,
+}
+
+impl Granularite {
+    /// Granularité d'un type de résultat BAN, absente pour un type inconnu
+    pub fn du_type(type_resultat: &str) -> Option<Self> {
+        match type_resultat {
+            "municipality" => Some(Granularite::Municipality),
+            "locality" => Some(Granularite::Locality),
+            "street" => Some(Granularite::Street),
+            "housenumber" => Some(Granularite::Housenumber),
+            _ => None,
+        }
+    }
+
+    /// Libellé de la colonne granularite
+    pub fn nom(self) -> &'static str {
+        match self {
+            Granularite::Municipality => "commune",
+            Granularite::Locality => "lieu_dit",
+            Granularite::Street => "voie",
+            Granularite::Housenumber => "numero",
+        }
+    }
+}
+
+/// Raison pour laquelle une adresse n'a pas pu être vérifiée
+///
+/// À ne pas confondre avec une adresse invalide : la ligne devra être
+/// vérifiée à nouveau.
+#[derive(Debug, Clone)]
+pub enum ErreurGeocodage {
+    /// Service injoignable : résolution DNS, connexion, délai dépassé
+    Reseau(String),
+    /// Le service a répondu avec un code HTTP d'erreur
+    Http(u16),
+    /// Réponse reçue mais illisible
+    Reponse(String),
+    /// Index local illisible
+    Index(String),
+    /// Paramètre du géocodeur incorrect (adresse du service…)
+    Configuration(String),
+}
+
+impl fmt::Display for ErreurGeocodage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErreurGeocodage::Reseau(detail) => write!(f, "service injoignable : {detail}"),
+            ErreurGeocodage::Http(statut) => write!(f, "erreur HTTP {statut}"),
+            ErreurGeocodage::Reponse(detail) => write!(f, "réponse illisible : {detail}"),
+            ErreurGeocodage::Index(detail) => write!(f, "index local illisible : {detail}"),
+            ErreurGeocodage::Configuration(detail) => f.write_str(detail),
+        }
+    }
+}
+
+impl std::error::Error for ErreurGeocodage {}
+
+impl From<reqwest::Error> for ErreurGeocodage {
+    fn from(erreur: reqwest::Error) -> Self {
+        // L'URL contient l'adresse demandée, déjà présente sur la ligne
+        let erreur = erreur.without_url();
+        if let Some(statut) = erreur.status() {
+            ErreurGeocodage::Http(statut.as_u16())
+        } else if erreur.is_decode() {
+            ErreurGeocodage::Reponse(erreur.to_string())
+        } else if erreur.is_builder() {
+            ErreurGeocodage::Configuration(erreur.to_string())
+        } else {
+            // Le message de reqwest seul ne donne pas la cause (DNS, refus…)
+            let mut detail = erreur.to_string();
+            let mut source = std::error::Error::source(&erreur);
+            while let Some(cause) = source {
+                detail = format!("{detail} : {cause}");
+                source = cause.source();
+            }
+            ErreurGeocodage::Reseau(detail)
+        }
+    }
+}
+
+/// Seules les réponses du géocodeur CSV sont lues avec csv
+impl From<csv::Error> for ErreurGeocodage {
+    fn from(erreur: csv::Error) -> Self {
+        ErreurGeocodage::Reponse(erreur.to_string())
+    }
+}
+
+/// Adresse de base d'un service HTTP, terminée par '/' pour que `Url::join`
+/// ajoute le chemin au lieu de remplacer le dernier segment
+pub fn url_de_base(base: &str) -> Result<Url, ErreurGeocodage> {
+    let base = if base.ends_with('/') {
+        base.to_string()
+    } else {
+        format!("{base}/")
+    };
+    Url::parse(&base)
+        .map_err(|e| ErreurGeocodage::Configuration(format!("adresse d'API invalide : {e}")))
+}
+
+/// Source de vérification des adresses
+///
+/// Les implémentations sont partagées entre plusieurs lignes et doivent donc
+/// être utilisables depuis plusieurs threads.
+pub trait Geocodeur: Send + Sync {
+    /// Candidats pour une adresse, du meilleur au moins bon (au plus `limite`)
+    fn geocoder(
+        &self,
+        requete: &Requete,
+        limite: usize,
+    ) -> Result<Vec<Correspondance>, ErreurGeocodage>;
+
+    /// Meilleur candidat pour chaque adresse d'un lot, dans l'ordre des requêtes
+    ///
+    /// Par défaut les adresses sont vérifiées une par une ; une erreur fait
+    /// échouer tout le lot.
+    fn geocoder_lot(
+        &self,
+        requetes: &[Requete],
+    ) -> Result<Vec<Option<Correspondance>>, ErreurGeocodage> {
+        requetes
+            .iter()
+            .map(|requete| Ok(self.geocoder(requete, 1)?.into_iter().next()))
+            .collect()
+    }
+}
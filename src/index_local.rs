@@ -0,0 +1,523 @@
+//! Validation hors ligne à partir des exports BAN « adresses-XX.csv »
+//!
+//! `index build` répartit les adresses des fichiers départementaux dans un
+//! fichier par code postal (`<index>/<cp>.tsv`). À la vérification, seul le
+//! fichier du code postal demandé est chargé, puis la voie la plus proche du
+//! libellé fourni est retenue.
+
+use crate::geocodeur::{Correspondance, ErreurGeocodage, Geocodeur, Requete};
+use crate::texte::normaliser;
+use crate::voie::est_type_voie;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Ligne d'un export BAN (séparateur « ; », seules les colonnes utiles sont lues)
+#[derive(Deserialize)]
+struct LigneBan {
+    id: String,
+    numero: String,
+    rep: String,
+    nom_voie: String,
+    code_postal: String,
+    code_insee: String,
+    nom_commune: String,
+    #[serde(default)]
+    nom_ancienne_commune: String,
+    lon: Option<f64>,
+    lat: Option<f64>,
+}
+
+/// Ligne d'un fichier de l'index
+#[derive(Serialize, Deserialize)]
+struct LigneIndex {
+    code_insee: String,
+    nom_commune: String,
+    nom_ancienne_commune: String,
+    nom_voie: String,
+    numero: String,
+    rep: String,
+    id: String,
+    lon: f64,
+    lat: f64,
+}
+
+/// Un code postal n'est utilisé comme nom de fichier que s'il a la bonne forme
+fn cp_valide(cp: &str) -> bool {
+    cp.len() == 5 && cp.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Construit l'index à partir d'exports BAN départementaux et renvoie le nombre d'adresses indexées
+///
+/// Les adresses des communes présentes dans les fichiers fournis sont
+/// remplacées, les autres sont conservées : on peut ajouter un département à
+/// la fois, y compris quand un code postal est partagé avec un département
+/// déjà indexé.
+pub fn construire(exports: &[PathBuf], dossier: &Path) -> Result<u64, Box<dyn Error>> {
+    fs::create_dir_all(dossier)?;
+    let mut total = 0;
+
+    for export in exports {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_path(export)?;
+
+        let mut par_cp: HashMap<String, Vec<LigneIndex>> = HashMap::new();
+        for ligne in rdr.deserialize::<LigneBan>() {
+            let ligne = ligne?;
+            let (Some(lon), Some(lat)) = (ligne.lon, ligne.lat) else {
+                continue;
+            };
+            if !cp_valide(&ligne.code_postal) {
+                continue;
+            }
+            par_cp
+                .entry(ligne.code_postal)
+                .or_default()
+                .push(LigneIndex {
+                    code_insee: ligne.code_insee,
+                    nom_commune: ligne.nom_commune,
+                    nom_ancienne_commune: ligne.nom_ancienne_commune,
+                    nom_voie: ligne.nom_voie,
+                    numero: ligne.numero,
+                    rep: ligne.rep,
+                    id: ligne.id,
+                    lon,
+                    lat,
+                });
+        }
+
+        for (cp, lignes) in par_cp {
+            // Adresses des autres communes du code postal, venues d'un autre export
+            let chemin = dossier.join(format!("{cp}.tsv"));
+            let communes: HashSet<&str> = lignes.iter().map(|l| l.code_insee.as_str()).collect();
+            let mut conservees = Vec::new();
+            if chemin.is_file() {
+                let mut rdr = csv::ReaderBuilder::new()
+                    .delimiter(b'\t')
+                    .has_headers(false)
+                    .from_path(&chemin)?;
+                for ligne in rdr.deserialize::<LigneIndex>() {
+                    let ligne = ligne?;
+                    if !communes.contains(ligne.code_insee.as_str()) {
+                        conservees.push(ligne);
+                    }
+                }
+            }
+            let mut wtr = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .has_headers(false)
+                .from_path(&chemin)?;
+            for ligne in conservees.iter().chain(&lignes) {
+                wtr.serialize(ligne)?;
+            }
+            wtr.flush()?;
+            total += lignes.len() as u64;
+        }
+    }
+    Ok(total)
+}
+
+/// Numéro présent dans une voie
+struct Numero {
+    numero: String,
+    rep: String,
+    id: String,
+    lon: f64,
+    lat: f64,
+}
+
+/// Voie d'une commune avec ses numéros
+struct Voie {
+    nom: String,
+    nom_normalise: String,
+    commune: String,
+    communes_normalisees: [String; 2],
+    code_insee: String,
+    numeros: Vec<Numero>,
+}
+
+impl Voie {
+    /// Position moyenne des numéros, utilisée quand le numéro demandé est absent
+    fn centre(&self) -> (f64, f64) {
+        let n = self.numeros.len() as f64;
+        let lon = self.numeros.iter().map(|n| n.lon).sum::<f64>() / n;
+        let lat = self.numeros.iter().map(|n| n.lat).sum::<f64>() / n;
+        (lon, lat)
+    }
+
+    fn dans_commune(&self, ville: &str) -> bool {
+        ville.is_empty()
+            || self.communes_normalisees.iter().any(|commune| {
+                // « PARIS » accepte « PARIS 2E ARRONDISSEMENT »
+                commune == ville
+                    || commune
+                        .strip_prefix(ville)
+                        .is_some_and(|reste| reste.starts_with(' '))
+            })
+    }
+}
+
+/// Indices de répétition équivalents (« 12B » et « 12 bis » désignent le même numéro)
+fn meme_rep(demande: &str, trouve: &str) -> bool {
+    let trouve = normaliser(trouve);
+    demande == trouve
+        || matches!(
+            (demande, trouve.as_str()),
+            ("B", "BIS") | ("T", "TER") | ("Q", "QUATER")
+        )
+}
+
+/// Mots trop courants dans les noms de voie pour les distinguer
+const MOTS_VIDES: &[&str] = &[
+    "A", "AU", "AUX", "D", "DE", "DES", "DU", "EN", "ET", "L", "LA", "LE", "LES", "SOUS", "SUR",
+];
+
+/// Mots distinctifs d'un nom de voie normalisé : sans type de voie en tête ni mots vides
+fn mots_distinctifs(nom: &str) -> Vec<&str> {
+    let mut mots: Vec<&str> = nom.split(' ').filter(|mot| !mot.is_empty()).collect();
+    if mots.len() > 1 && est_type_voie(mots[0]) {
+        mots.remove(0);
+    }
+    mots.retain(|mot| !MOTS_VIDES.contains(mot));
+    mots
+}
+
+/// Part des mots de `a` retrouvés dans `b`, pondérée par leur longueur ; un mot
+/// proche (faute de frappe) compte selon sa distance d'édition
+fn couverture(a: &[&str], b: &[&str]) -> f64 {
+    let total: usize = a.iter().map(|mot| mot.len()).sum();
+    let retrouve: f64 = a
+        .iter()
+        .map(|mot| {
+            let proche = b
+                .iter()
+                .map(|autre| strsim::normalized_levenshtein(mot, autre))
+                .fold(0.0, f64::max);
+            proche * mot.len() as f64
+        })
+        .sum();
+    retrouve / total as f64
+}
+
+/// Similarité de deux noms de voie normalisés, entre 0 et 1
+///
+/// Les noms français partagent de longs débuts (« RUE DE LA », « AVENUE DU
+/// GENERAL ») : seuls les mots distinctifs sont comparés, dans les deux sens.
+fn similarite_voie(demande: &str, voie: &str) -> f64 {
+    let (a, b) = (mots_distinctifs(demande), mots_distinctifs(voie));
+    if a.is_empty() || b.is_empty() {
+        return strsim::normalized_levenshtein(demande, voie);
+    }
+    (couverture(&a, &b) + couverture(&b, &a)) / 2.0
+}
+
+/// Sépare le numéro et l'indice de répétition du reste du libellé normalisé
+fn decouper_numero(adresse: &str) -> (Option<(String, String)>, String) {
+    let mut mots: Vec<&str> = adresse.split(' ').collect();
+    let Some(premier) = mots.first() else {
+        return (None, String::new());
+    };
+    let chiffres: String = premier.chars().take_while(char::is_ascii_digit).collect();
+    if chiffres.is_empty() {
+        return (None, adresse.to_string());
+    }
+    let mut rep = premier[chiffres.len()..].to_string();
+    mots.remove(0);
+    if rep.is_empty()
+        && mots.len() > 1
+        && matches!(mots[0], "BIS" | "TER" | "QUATER" | "A" | "B" | "C" | "D")
+    {
+        rep = mots.remove(0).to_string();
+    }
+    let numero = chiffres.trim_start_matches('0').to_string();
+    (Some((numero, rep)), mots.join(" "))
+}
+
+/// Index local, chargé à la demande code postal par code postal
+pub struct IndexLocal {
+    dossier: PathBuf,
+    charges: Mutex<HashMap<String, Arc<Vec<Voie>>>>,
+}
+
+impl IndexLocal {
+    pub fn ouvrir(dossier: &Path) -> Result<Self, Box<dyn Error>> {
+        if !dossier.is_dir() {
+            return Err(format!(
+                "index introuvable : {} (lancer « index build » d'abord)",
+                dossier.display()
+            )
+            .into());
+        }
+        Ok(IndexLocal {
+            dossier: dossier.to_path_buf(),
+            charges: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn voies(&self, cp: &str) -> Result<Arc<Vec<Voie>>, ErreurGeocodage> {
+        if let Some(voies) = self.charges.lock().unwrap().get(cp) {
+            return Ok(Arc::clone(voies));
+        }
+
+        let chemin = self.dossier.join(format!("{cp}.tsv"));
+        let mut voies: Vec<Voie> = Vec::new();
+        if chemin.is_file() {
+            let mut rdr = csv::ReaderBuilder::new()
+                .delimiter(b'\t')
+                .has_headers(false)
+                .from_path(&chemin)
+                .map_err(|e| ErreurGeocodage::Index(e.to_string()))?;
+            let mut positions: HashMap<(String, String), usize> = HashMap::new();
+            for ligne in rdr.deserialize::<LigneIndex>() {
+                let ligne = ligne.map_err(|e| ErreurGeocodage::Index(e.to_string()))?;
+                let cle = (ligne.code_insee.clone(), ligne.nom_voie.clone());
+                let position = *positions.entry(cle).or_insert_with(|| {
+                    voies.push(Voie {
+                        nom_normalise: normaliser(&ligne.nom_voie),
+                        nom: ligne.nom_voie.clone(),
+                        commune: ligne.nom_commune.clone(),
+                        communes_normalisees: [
+                            normaliser(&ligne.nom_commune),
+                            normaliser(&ligne.nom_ancienne_commune),
+                        ],
+                        code_insee: ligne.code_insee.clone(),
+                        numeros: Vec::new(),
+                    });
+                    voies.len() - 1
+                });
+                voies[position].numeros.push(Numero {
+                    numero: ligne.numero,
+                    rep: ligne.rep,
+                    id: ligne.id,
+                    lon: ligne.lon,
+                    lat: ligne.lat,
+                });
+            }
+        }
+
+        let voies = Arc::new(voies);
+        self.charges
+            .lock()
+            .unwrap()
+            .insert(cp.to_string(), Arc::clone(&voies));
+        Ok(voies)
+    }
+}
+
+/// Candidat pour une voie : le numéro demandé s'il existe, sinon la voie elle-même
+fn correspondance_voie(
+    voie: &Voie,
+    similarite: f64,
+    numero: Option<&(String, String)>,
+    cp: &str,
+) -> Correspondance {
+    let trouve = numero.and_then(|(numero, rep)| {
+        let meme_numero = |n: &&Numero| n.numero.trim_start_matches('0') == numero;
+        voie.numeros
+            .iter()
+            .filter(meme_numero)
+            .find(|n| meme_rep(rep, &n.rep))
+            .map(|n| (n, 1.0))
+            .or_else(|| voie.numeros.iter().find(meme_numero).map(|n| (n, 0.9)))
+    });
+
+    match trouve {
+        Some((n, penalite)) => {
+            let numero = [n.numero.as_str(), n.rep.as_str()]
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Correspondance {
+                label: format!("{numero} {} {cp} {}", voie.nom, voie.commune),
+                score: similarite * penalite,
+                type_resultat: "housenumber".to_string(),
+                id: n.id.clone(),
+                citycode: voie.code_insee.clone(),
+                postcode: Some(cp.to_string()),
+                city: voie.commune.clone(),
+                housenumber: Some(numero),
+                street: Some(voie.nom.clone()),
+                longitude: n.lon,
+                latitude: n.lat,
+            }
+        }
+        None => {
+            // Numéro absent de la voie : on se rabat sur la voie elle-même
+            let penalite = if numero.is_some() { 0.8 } else { 1.0 };
+            let (lon, lat) = voie.centre();
+            let id = voie.numeros[0]
+                .id
+                .rsplit_once('_')
+                .map_or(voie.numeros[0].id.as_str(), |(voie, _)| voie)
+                .to_string();
+            Correspondance {
+                label: format!("{} {cp} {}", voie.nom, voie.commune),
+                score: similarite * penalite,
+                type_resultat: "street".to_string(),
+                id,
+                citycode: voie.code_insee.clone(),
+                postcode: Some(cp.to_string()),
+                city: voie.commune.clone(),
+                housenumber: None,
+                street: Some(voie.nom.clone()),
+                longitude: lon,
+                latitude: lat,
+            }
+        }
+    }
+}
+
+impl Geocodeur for IndexLocal {
+    /// Équivalent local de la recherche BAN : voies du code postal et de la
+    /// commune, classées par proximité avec le libellé fourni
+    fn geocoder(
+        &self,
+        requete: &Requete,
+        limite: usize,
+    ) -> Result<Vec<Correspondance>, ErreurGeocodage> {
+        let cp = requete.cp.trim();
+        if !cp_valide(cp) {
+            return Ok(Vec::new());
+        }
+        let voies = self.voies(cp)?;
+        let ville = normaliser(requete.ville);
+        let candidates: Vec<&Voie> = voies.iter().filter(|v| v.dans_commune(&ville)).collect();
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (numero, nom_voie) = decouper_numero(&normaliser(requete.adresse));
+        // Sans nom de voie, la commune seule ne suffit pas à valider l'adresse
+        if nom_voie.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut proches: Vec<(&Voie, f64)> = candidates
+            .into_iter()
+            .map(|voie| (voie, similarite_voie(&nom_voie, &voie.nom_normalise)))
+            .collect();
+        proches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        proches.truncate(limite);
+
+        let mut correspondances: Vec<Correspondance> = proches
+            .into_iter()
+            .map(|(voie, similarite)| correspondance_voie(voie, similarite, numero.as_ref(), cp))
+            .collect();
+        correspondances.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(correspondances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debut_commun_non_recompense() {
+        assert!(similarite_voie("RUE DE LA GARE", "RUE DE LA PAIX") < 0.7);
+        assert!(similarite_voie("RUE DES LILAS", "RUE DES LYS") < 0.7);
+        assert!(similarite_voie("AVENUE DU GENERAL DE GAULLE", "AVENUE DU GENERAL LECLERC") < 0.7);
+    }
+
+    #[test]
+    fn meme_voie_reconnue() {
+        assert_eq!(similarite_voie("RUE DE LA PAIX", "RUE DE LA PAIX"), 1.0);
+        assert_eq!(similarite_voie("R DE LA PAIX", "RUE DE LA PAIX"), 1.0);
+        assert!(similarite_voie("RUE DE LA PAIZ", "RUE DE LA PAIX") >= 0.7);
+    }
+
+    #[test]
+    fn autre_voie_non_validee() {
+        let dossier = std::env::temp_dir().join(format!("index_test_{}", std::process::id()));
+        fs::create_dir_all(&dossier).unwrap();
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_path(dossier.join("75002.tsv"))
+            .unwrap();
+        wtr.serialize(LigneIndex {
+            code_insee: "75102".to_string(),
+            nom_commune: "Paris".to_string(),
+            nom_ancienne_commune: String::new(),
+            nom_voie: "Rue de la Paix".to_string(),
+            numero: "12".to_string(),
+            rep: String::new(),
+            id: "75102_7000_00012".to_string(),
+            lon: 2.33,
+            lat: 48.87,
+        })
+        .unwrap();
+        wtr.flush().unwrap();
+
+        let index = IndexLocal::ouvrir(&dossier).unwrap();
+        let requete = Requete {
+            adresse: "12 rue de la Gare",
+            cp: "75002",
+            ville: "Paris",
+        };
+        let resultats = index.geocoder(&requete, 1).unwrap();
+        fs::remove_dir_all(&dossier).unwrap();
+        assert!(resultats.iter().all(|r| r.score < 0.7));
+    }
+
+    #[test]
+    fn departements_ajoutes_un_a_un() {
+        let dossier = std::env::temp_dir().join(format!("index_ajout_{}", std::process::id()));
+        fs::create_dir_all(&dossier).unwrap();
+        let entete = "id;numero;rep;nom_voie;code_postal;code_insee;nom_commune;lon;lat";
+        let premier = dossier.join("adresses-01.csv");
+        fs::write(
+            &premier,
+            format!("{entete}\n01001_1_00001;1;;Rue A;01200;01001;Alpha;5.1;46.1\n"),
+        )
+        .unwrap();
+        let second = dossier.join("adresses-74.csv");
+        fs::write(
+            &second,
+            format!("{entete}\n74002_1_00002;2;;Rue B;01200;74002;Beta;5.9;46.0\n"),
+        )
+        .unwrap();
+        let index = dossier.join("index");
+        construire(std::slice::from_ref(&premier), &index).unwrap();
+        construire(&[second], &index).unwrap();
+        // Reconstruire un département remplace ses communes sans les dupliquer
+        construire(&[premier], &index).unwrap();
+        let contenu = fs::read_to_string(index.join("01200.tsv")).unwrap();
+        fs::remove_dir_all(&dossier).unwrap();
+        assert_eq!(contenu.matches("Rue A").count(), 1);
+        assert_eq!(contenu.matches("Rue B").count(), 1);
+    }
+
+    #[test]
+    fn commune_seule_non_validee() {
+        let dossier = std::env::temp_dir().join(format!("index_commune_{}", std::process::id()));
+        let export = std::env::temp_dir().join(format!("adresses_{}.csv", std::process::id()));
+        fs::write(
+            &export,
+            "id;numero;rep;nom_voie;code_postal;code_insee;nom_commune;lon;lat\n\
+             59350_1_00012;12;;Rue de la Paix;59000;59350;Lille;3.06;50.63\n",
+        )
+        .unwrap();
+        construire(std::slice::from_ref(&export), &dossier).unwrap();
+        let index = IndexLocal::ouvrir(&dossier).unwrap();
+        let resultats: Vec<_> = ["", "12"]
+            .into_iter()
+            .map(|adresse| {
+                let requete = Requete {
+                    adresse,
+                    cp: "59000",
+                    ville: "Lille",
+                };
+                index.geocoder(&requete, 1).unwrap()
+            })
+            .collect();
+        fs::remove_dir_all(&dossier).unwrap();
+        fs::remove_file(&export).unwrap();
+        assert!(resultats.iter().all(Vec::is_empty));
+    }
+}
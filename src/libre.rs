@@ -0,0 +1,160 @@
+//! Adresse saisie en texte libre dans une seule colonne
+//!
+//! « 12 rue de la Paix 75002 Paris » est découpée autour du code postal ; sans
+//! code postal, ou quand la ville le précède, la dernière virgule sépare la
+//! voie de la ville. La voie peut encore contenir un complément (« Résidence
+//! Les Pins, 12 rue de la Paix ») : ses parties sont rendues par `parties`.
+
+/// Éléments extraits d'une adresse en texte libre, vides s'ils sont absents
+pub struct AdresseDecoupee<'a> {
+    pub voie: &'a str,
+    pub cp: &'a str,
+    pub ville: &'a str,
+}
+
+fn nettoyer(partie: &str) -> &str {
+    partie.trim_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '-'))
+}
+
+/// Retire la mention CEDEX et son numéro, que le géocodeur ne connaît pas
+fn sans_cedex(ville: &str) -> &str {
+    let majuscules = ville.to_uppercase();
+    match majuscules.rfind("CEDEX") {
+        // La mention est en ASCII : la position vaut aussi dans le texte d'origine
+        Some(position) if majuscules.len() == ville.len() => nettoyer(&ville[..position]),
+        _ => ville,
+    }
+}
+
+/// Découpe une adresse autour de son dernier code postal (cinq chiffres isolés)
+pub fn decouper(texte: &str) -> AdresseDecoupee<'_> {
+    let separateur = |c: char| c.is_whitespace() || c == ',';
+    let mut cp = None;
+    let mut debut = None;
+    for (position, c) in texte.char_indices().chain([(texte.len(), ' ')]) {
+        match (separateur(c), debut) {
+            (true, Some(d)) => {
+                let mot = &texte[d..position];
+                if mot.len() == 5 && mot.bytes().all(|b| b.is_ascii_digit()) {
+                    cp = Some((d, position));
+                }
+                debut = None;
+            }
+            (false, None) => debut = Some(position),
+            _ => {}
+        }
+    }
+
+    match cp {
+        // « 12 rue de la Paix, Paris 75002 » : la ville précède le code postal
+        Some((d, f)) if nettoyer(&texte[f..]).is_empty() && texte[..d].contains(',') => {
+            let (voie, ville) = texte[..d].rsplit_once(',').unwrap_or_default();
+            AdresseDecoupee {
+                voie: nettoyer(voie),
+                cp: &texte[d..f],
+                ville: sans_cedex(nettoyer(ville)),
+            }
+        }
+        Some((d, f)) => AdresseDecoupee {
+            voie: nettoyer(&texte[..d]),
+            cp: &texte[d..f],
+            ville: sans_cedex(nettoyer(&texte[f..])),
+        },
+        None => match texte.rsplit_once(',') {
+            Some((voie, ville)) => AdresseDecoupee {
+                voie: nettoyer(voie),
+                cp: "",
+                ville: sans_cedex(nettoyer(ville)),
+            },
+            None => AdresseDecoupee {
+                voie: nettoyer(texte),
+                cp: "",
+                ville: "",
+            },
+        },
+    }
+}
+
+/// Vrai pour un numéro seul, éventuellement suivi de son indice (« 12 », « 12 bis »)
+fn est_numero(partie: &str) -> bool {
+    partie.starts_with(|c: char| c.is_ascii_digit()) && partie.split_whitespace().count() <= 2
+}
+
+/// Parties de la voie séparées par des virgules ; un numéro seul
+/// (« 12, rue de la Paix ») reste attaché à la partie qui le suit
+pub fn parties(voie: &str) -> Vec<&str> {
+    let mut parties = Vec::new();
+    let mut debut = 0;
+    for (position, _) in voie.match_indices(',').chain([(voie.len(), "")]) {
+        let partie = nettoyer(&voie[debut..position]);
+        if est_numero(partie) && position < voie.len() {
+            continue;
+        }
+        if !partie.is_empty() {
+            parties.push(partie);
+        }
+        debut = position + 1;
+    }
+    parties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(texte: &str) -> (&str, &str, &str) {
+        let decoupee = decouper(texte);
+        (decoupee.voie, decoupee.cp, decoupee.ville)
+    }
+
+    #[test]
+    fn decoupage_autour_du_code_postal() {
+        assert_eq!(
+            elements("12 rue de la Paix 75002 Paris"),
+            ("12 rue de la Paix", "75002", "Paris")
+        );
+        assert_eq!(
+            elements("Résidence Les Pins, 12 rue de la Paix, 75002 Paris"),
+            ("Résidence Les Pins, 12 rue de la Paix", "75002", "Paris")
+        );
+        assert_eq!(
+            elements("12 rue de la Paix, Paris 75002"),
+            ("12 rue de la Paix", "75002", "Paris")
+        );
+    }
+
+    #[test]
+    fn decoupage_sans_code_postal() {
+        assert_eq!(
+            elements("12 rue de la Paix, Paris"),
+            ("12 rue de la Paix", "", "Paris")
+        );
+        assert_eq!(elements("12 rue de la Paix"), ("12 rue de la Paix", "", ""));
+    }
+
+    #[test]
+    fn cedex_retire() {
+        assert_eq!(
+            elements("BP 12, 59000 Lille CEDEX 9"),
+            ("BP 12", "59000", "Lille")
+        );
+        assert_eq!(
+            elements("3 av. Foch 59800 Lille Cedex"),
+            ("3 av. Foch", "59800", "Lille")
+        );
+    }
+
+    #[test]
+    fn parties_de_la_voie() {
+        assert_eq!(
+            parties("Résidence Les Pins, 12 rue de la Paix"),
+            vec!["Résidence Les Pins", "12 rue de la Paix"]
+        );
+        assert_eq!(parties("12, rue de la Paix"), vec!["12, rue de la Paix"]);
+        assert_eq!(
+            parties("Bât A, 3 bis, avenue Foch"),
+            vec!["Bât A", "3 bis, avenue Foch"]
+        );
+        assert_eq!(parties("12 rue de la Paix,"), vec!["12 rue de la Paix"]);
+    }
+}
@@ -1,73 +1,914 @@
-use clap::Parser;
-use csv::{ReaderBuilder, WriterBuilder};
+mod ban;
+mod base;
+mod cache;
+mod codes_postaux;
+mod colonnes;
+mod dialecte;
+mod encodage;
+mod geocodeur;
+mod index_local;
+mod libre;
+mod normalisation;
+mod osm;
+mod reprise;
+mod reseau;
+mod sortie;
+mod tableur;
+mod texte;
+mod voie;
+
+use ban::ClientBan;
+use base::{SortieMiseAJour, SortieTable, SourceSqlite};
+use cache::{CacheDisque, GeocodeurEnCache};
+use clap::{Parser, Subcommand, ValueEnum};
+use codes_postaux::{CodesPostaux, ControleCp};
+use colonnes::{Champ, Colonnes};
+use csv::StringRecord;
+use geocodeur::{Correspondance, ErreurGeocodage, Geocodeur, Granularite, Requete};
+use index_local::IndexLocal;
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::Url;
-use serde::{Deserialize, Serialize};
+use osm::{ClientNominatim, ClientPhoton};
+use reseau::ClientHttp;
+use sortie::{Sortie, SortieCsv, SortieGeoJson, SortieJsonl};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+use tableur::ClasseurSortie;
+use texte::nom_commune;
 
 /// Arguments ligne de commande
 #[derive(Parser)]
-#[command(name = "Adresse Checker", version)]
+#[command(
+    name = "Adresse Checker",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Nom du fichier d'entrée (UTF-8, tabulation)
-    input_file: String,
+    #[command(subcommand)]
+    commande: Option<Commande>,
+
+    /// Nom du fichier d'entrée : texte délimité (séparateur et encodage détectés), classeur .xlsx, .xls, .ods,
+    /// ou base SQLite (« sqlite:clients.db?table=clients », « sqlite:clients.db?query=SELECT … »)
+    #[arg(required = true)]
+    input_file: Option<String>,
 
     /// Nombre de lignes à traiter (hors en-tête)
-    lines_to_check: usize,
+    #[arg(required = true)]
+    lines_to_check: Option<usize>,
+
+    /// Format du fichier _chk, celui de l'entrée par défaut (xlsx pour un classeur) ;
+    /// une base SQLite reçoit les résultats dans une table, sauf si un format est donné
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Table créée dans la base SQLite d'entrée pour les résultats, <table>_chk par défaut
+    #[arg(long, value_name = "TABLE", conflicts_with = "update_key")]
+    output_table: Option<String>,
+
+    /// Remplace la table de --output-table si elle existe déjà (<table>_chk est toujours remplacée)
+    #[arg(long)]
+    replace_table: bool,
+
+    /// Complète la table SQLite d'entrée au lieu de créer une table : les colonnes de
+    /// validation y sont ajoutées et chaque ligne est retrouvée par cette colonne clé
+    #[arg(long, value_name = "COLONNE")]
+    update_key: Option<String>,
+
+    /// Feuille à lire dans un classeur .xlsx, .xls ou .ods (nom ou numéro), la première par défaut
+    #[arg(long)]
+    sheet: Option<String>,
+
+    /// Encodage du fichier d'entrée (utf-8, windows-1252, iso-8859-15, utf-16le…) au lieu de la détection
+    #[arg(long)]
+    encoding: Option<String>,
+
+    /// Encodage du fichier _chk (windows-1252, iso-8859-15…), UTF-8 par défaut
+    #[arg(long)]
+    output_encoding: Option<String>,
+
+    /// Séparateur du fichier d'entrée (« tab », « ; », « , », « | »…) au lieu de la détection
+    #[arg(long, value_parser = dialecte::caractere)]
+    delimiter: Option<u8>,
+
+    /// Séparateur du fichier _chk, celui de l'entrée par défaut
+    #[arg(long, value_parser = dialecte::caractere)]
+    output_delimiter: Option<u8>,
+
+    /// Caractère d'encadrement des champs, en entrée et en sortie
+    #[arg(long, value_parser = dialecte::caractere, default_value = "\"")]
+    quote: u8,
+
+    /// Échappement des guillemets dans un champ, en entrée et en sortie
+    #[arg(long, value_enum, default_value_t = dialecte::Echappement::Double)]
+    escape: dialecte::Echappement,
+
+    /// Le fichier d'entrée n'a pas de ligne d'en-tête (colonnes nom, adresse, cp, ville, contact sauf --column)
+    #[arg(long)]
+    no_header: bool,
+
+    /// Colonne d'un champ non reconnue d'après l'en-tête : « adresse=Adresse 1 », « cp=3 » (répétable)
+    #[arg(long, value_name = "CHAMP=COLONNE", value_parser = colonnes::designation)]
+    column: Vec<colonnes::Designation>,
+
+    /// Fichier de correspondance des colonnes, une ligne « champ=colonne » par champ
+    #[arg(long)]
+    columns_file: Option<PathBuf>,
+
+    /// La colonne adresse contient l'adresse complète (« 12 rue de la Paix 75002 Paris ») :
+    /// le code postal et la ville en sont extraits
+    #[arg(long)]
+    full_address: bool,
+
+    /// Envoie la ligne de voie telle quelle, sans développer les abréviations
+    /// (« BD », « AV », « ST »…) ni retirer la ponctuation
+    #[arg(long)]
+    no_normalize: bool,
+
+    /// Colonne (nom ou numéro) après laquelle insérer les colonnes de validation, 0 pour les placer en tête
+    #[arg(long, value_name = "COLONNE")]
+    insert_after: Option<String>,
+
+    /// Traitement des caractères absents de l'encodage de sortie
+    #[arg(long, value_enum, default_value_t = encodage::Politique::Replace)]
+    unmappable: encodage::Politique,
+
+    /// Source utilisée pour vérifier les adresses
+    #[arg(long, value_enum, default_value_t = Backend::Ban)]
+    backend: Backend,
+
+    /// Dossier de l'index local (--backend local)
+    #[arg(long, default_value = "index")]
+    index_dir: PathBuf,
+
+    /// Score minimal pour qu'une adresse soit considérée comme valide
+    #[arg(long, default_value_t = 0.7)]
+    threshold: f64,
+
+    /// Score à partir duquel une adresse sous le seuil de validité est classée « a_verifier »
+    #[arg(long)]
+    review_threshold: Option<f64>,
+
+    /// Base officielle des codes postaux de La Poste (CSV) : contrôle hors ligne de la
+    /// cohérence code postal / commune, indépendant du géocodeur
+    #[arg(long, value_name = "FICHIER")]
+    postcode_file: Option<PathBuf>,
+
+    /// Mode strict : le code postal (ou code INSEE) et la commune trouvés doivent
+    /// correspondre à ceux de l'entrée, sinon l'adresse est « divergent »
+    #[arg(long)]
+    strict: bool,
+
+    /// Précision minimale d'une adresse valide : housenumber exige le numéro, street
+    /// accepte une voie sans numéro… ; en dessous, l'adresse est « imprecis »
+    #[arg(long, value_enum, value_name = "GRANULARITE")]
+    min_granularity: Option<Granularite>,
+
+    /// Envoyer les adresses par lots (géocodeur CSV /search/csv/ pour ban et addok)
+    #[arg(long)]
+    batch: bool,
+
+    /// Nombre de lignes par lot en mode --batch
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Nombre de lots vérifiés en parallèle (l'ordre des lignes est conservé en sortie)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=64))]
+    workers: u64,
+
+    /// Requêtes par seconde, tous threads confondus (0 : sans limite ; par défaut selon le backend)
+    #[arg(long)]
+    rate: Option<f64>,
+
+    /// Nombre de nouvelles tentatives après un 429, une erreur 5xx ou réseau
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+
+    /// Reprendre une vérification interrompue là où elle s'est arrêtée
+    #[arg(long)]
+    resume: bool,
+
+    /// Ne pas lire ni écrire le cache disque (les doublons du fichier restent dédupliqués)
+    #[arg(long)]
+    no_cache: bool,
+
+    #[command(flatten)]
+    cache: OptionsCache,
+
+    /// Adresse de base du service (par défaut l'instance publique du backend choisi)
+    #[arg(long)]
+    api_url: Option<String>,
+}
+
+/// Sous-commandes annexes à la vérification d'un fichier
+#[derive(Subcommand)]
+enum Commande {
+    /// Gestion de l'index utilisé par --backend local
+    Index {
+        #[command(subcommand)]
+        action: ActionIndex,
+    },
+    /// Gestion du cache des réponses
+    Cache {
+        #[command(subcommand)]
+        action: ActionCache,
+    },
+}
+
+#[derive(Subcommand)]
+enum ActionIndex {
+    /// Construit (ou complète) l'index à partir d'exports BAN « adresses-XX.csv »
+    Build {
+        /// Fichiers départementaux téléchargés depuis adresse.data.gouv.fr
+        #[arg(required = true)]
+        exports: Vec<PathBuf>,
+
+        /// Dossier de l'index
+        #[arg(long, default_value = "index")]
+        index_dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ActionCache {
+    /// Affiche le nombre de réponses en cache par source
+    Stats {
+        #[command(flatten)]
+        options: OptionsCache,
+    },
+    /// Supprime les réponses expirées (ou toutes avec --all)
+    Purge {
+        /// Vider entièrement le cache
+        #[arg(long)]
+        all: bool,
+
+        #[command(flatten)]
+        options: OptionsCache,
+    },
+}
+
+/// Emplacement et durée de validité du cache disque
+#[derive(clap::Args)]
+struct OptionsCache {
+    /// Fichier SQLite du cache des réponses
+    #[arg(long, default_value = cache::FICHIER_PAR_DEFAUT)]
+    cache_file: PathBuf,
+
+    /// Durée de validité d'une réponse en cache, en jours
+    #[arg(long, default_value_t = 30)]
+    cache_ttl: u64,
+}
+
+impl OptionsCache {
+    fn ouvrir(&self) -> Result<CacheDisque, Box<dyn Error>> {
+        let duree_de_vie = Duration::from_secs(self.cache_ttl * 24 * 3600);
+        CacheDisque::ouvrir(&self.cache_file, duree_de_vie)
+    }
+}
+
+/// Format du fichier _chk
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// Texte séparé par des virgules
+    Csv,
+    /// Texte séparé par des tabulations
+    Tsv,
+    /// Un objet JSON par ligne
+    Jsonl,
+    /// FeatureCollection de points, colonnes en propriétés
+    Geojson,
+    /// Classeur Excel, colonne adresse_valide en couleur
+    Xlsx,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+            Format::Jsonl => "jsonl",
+            Format::Geojson => "geojson",
+            Format::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// Source de vérification des adresses
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Backend {
+    /// API Adresse (api-adresse.data.gouv.fr)
+    Ban,
+    /// Instance Addok auto-hébergée (--api-url obligatoire)
+    Addok,
+    /// Nominatim (OpenStreetMap)
+    Nominatim,
+    /// Photon (OpenStreetMap)
+    Photon,
+    /// Index construit avec « index build », sans accès réseau
+    Local,
+}
+
+impl Backend {
+    /// Géocodeur correspondant au backend choisi
+    fn geocodeur(self, args: &Args) -> Result<Box<dyn Geocodeur>, Box<dyn Error>> {
+        let url = |defaut: &str| args.api_url.clone().unwrap_or_else(|| defaut.to_string());
+        // --rate 0 lève la limite ; sans --rate, celle de l'instance publique s'applique
+        let http = |defaut: Option<f64>| {
+            let debit = args.rate.or(defaut).filter(|debit| *debit > 0.0);
+            ClientHttp::new(debit, args.max_retries)
+        };
+        let geocodeur: Box<dyn Geocodeur> = match self {
+            Backend::Ban => Box::new(ClientBan::new(
+                &url(ban::URL_API_PUBLIQUE),
+                http(Some(ban::DEBIT_API_PUBLIQUE))?,
+            )?),
+            Backend::Addok => {
+                let url = args
+                    .api_url
+                    .as_deref()
+                    .ok_or("--backend addok nécessite --api-url")?;
+                Box::new(ClientBan::new(url, http(None)?)?)
+            }
+            Backend::Nominatim => Box::new(ClientNominatim::new(
+                &url(osm::URL_NOMINATIM),
+                http(Some(osm::DEBIT_NOMINATIM))?,
+            )?),
+            Backend::Photon => Box::new(ClientPhoton::new(
+                &url(osm::URL_PHOTON),
+                http(Some(osm::DEBIT_PHOTON))?,
+            )?),
+            Backend::Local => Box::new(IndexLocal::ouvrir(&args.index_dir)?),
+        };
+        Ok(geocodeur)
+    }
 }
 
 /// Structure pour lire les lignes du fichier
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 struct InputRecord {
-    nom: String,
+    /// Ligne de voie, envoyée au géocodeur
     adresse: String,
+    /// Autres lignes d'adresse (bâtiment, résidence, lieu-dit…)
+    complement: String,
+    /// Code postal et ville lus dans l'adresse complète (--full-address)
+    extrait: Option<(String, String)>,
     cp: String,
     ville: String,
-    contact: String,
+    /// Voie et ville normalisées, envoyées au géocodeur à la place de celles lues
+    normalisee: Option<(String, String)>,
+    /// Ligne d'origine, recopiée telle quelle en sortie
+    ligne: StringRecord,
 }
 
-/// Structure pour écrire les lignes avec le champ en plus
-#[derive(Debug, Serialize)]
+impl InputRecord {
+    fn depuis(
+        ligne: StringRecord,
+        colonnes: &Colonnes,
+        adresse_complete: bool,
+        normaliser: bool,
+    ) -> Self {
+        let lignes = [Champ::Adresse, Champ::Adresse2, Champ::Adresse3]
+            .map(|champ| colonnes.valeur(&ligne, champ));
+        let (voie, mut complement) = voie::separer(&lignes.each_ref().map(String::as_str));
+        let mut cp = colonnes.valeur(&ligne, Champ::Cp);
+        // Code postal passé par une cellule numérique d'Excel : 1000 pour 01000
+        if cp.len() == 4 && cp.bytes().all(|b| b.is_ascii_digit()) {
+            cp.insert(0, '0');
+        }
+        let mut ville = colonnes.valeur(&ligne, Champ::Ville);
+        let mut adresse = voie.to_string();
+        let mut extrait = None;
+        if adresse_complete {
+            // Les colonnes cp et ville, si elles existent et sont remplies, priment
+            let decoupee = libre::decouper(voie);
+            if cp.trim().is_empty() {
+                cp = decoupee.cp.to_string();
+            }
+            if ville.trim().is_empty() {
+                ville = decoupee.ville.to_string();
+            }
+            // Un complément écrit avant la voie (« Résidence Les Pins, 12 rue… »)
+            // n'est pas envoyé au géocodeur
+            let (voie_libre, complement_libre) = voie::separer(&libre::parties(decoupee.voie));
+            adresse = voie_libre.to_string();
+            complement.splice(0..0, complement_libre);
+            extrait = Some((decoupee.cp.to_string(), decoupee.ville.to_string()));
+        }
+        let normalisee = normaliser.then(|| {
+            (
+                normalisation::normaliser_voie(&adresse),
+                normalisation::normaliser_commune(&ville),
+            )
+        });
+        InputRecord {
+            adresse,
+            complement: complement.join(", "),
+            cp,
+            ville,
+            normalisee,
+            extrait,
+            ligne,
+        }
+    }
+
+    fn requete(&self) -> Requete<'_> {
+        let (adresse, ville) = match &self.normalisee {
+            Some((adresse, ville)) => (adresse, ville),
+            None => (&self.adresse, &self.ville),
+        };
+        Requete {
+            adresse,
+            cp: self.cp.trim(),
+            ville,
+        }
+    }
+}
+
+/// Ligne d'entrée complétée par les colonnes de validation
+#[derive(Debug)]
 struct OutputRecord {
-    nom: String,
-    adresse: String,
-    cp: String,
-    ville: String,
-    contact: String,
+    entree: StringRecord,
+    adresse_voie: String,
+    complement_adresse: String,
+    /// Texte de la requête envoyée au géocodeur
+    requete: String,
+    extrait: Option<(String, String)>,
+    /// Contrôle du code postal avec la base de La Poste (--postcode-file)
+    controle_cp: Option<ControleCp>,
+    /// Composants du résultat qui diffèrent de l'entrée (--strict)
+    divergence: Option<Vec<&'static str>>,
     adresse_valide: bool,
+    qualite: Qualite,
+    statut: Statut,
+    /// Précision du résultat, d'après son type
+    granularite: Option<Granularite>,
+    erreur: Option<String>,
+    ban_label: Option<String>,
+    ban_score: Option<f64>,
+    ban_type: Option<String>,
+    ban_id: Option<String>,
+    ban_citycode: Option<String>,
+    ban_postcode: Option<String>,
+    ban_city: Option<String>,
+    /// Numéro et voie trouvés, pour le découpage de la voie
+    ban_housenumber: Option<String>,
+    ban_street: Option<String>,
+    longitude: Option<f64>,
+    latitude: Option<f64>,
 }
 
-/// Vérification de l'adresse via l'API publique
-fn verifier_adresse_api(adresse: &str, cp: &str, ville: &str) -> bool {
-    let client = reqwest::blocking::Client::new();
-    let query = format!("{adresse}, {cp} {ville}");
-
-    let mut url = Url::parse("https://api-adresse.data.gouv.fr/search/").unwrap();
-    url.query_pairs_mut()
-        .append_pair("q", &query)
-        .append_pair("limit", "1");
-
-    if let Ok(resp) = client.get(url).send() {
-        if let Ok(json) = resp.json::<serde_json::Value>() {
-            if let Some(features) = json.get("features") {
-                if let Some(first) = features.get(0) {
-                    if let Some(score) = first
-                        .get("properties")
-                        .and_then(|p| p.get("score"))
-                        .and_then(|s| s.as_f64())
-                    {
-                        return score >= 0.7;
-                    }
-                }
+/// Issue de la vérification d'une ligne
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Statut {
+    /// Adresse trouvée avec un score suffisant
+    Valide,
+    /// Adresse trouvée mais avec un score trop faible
+    Invalide,
+    /// Adresse trouvée, mais moins précise que --min-granularity
+    Imprecis,
+    /// Adresse trouvée dans une autre commune ou sous un autre code postal (--strict)
+    Divergent,
+    /// Le géocodeur n'a renvoyé aucun résultat
+    Introuvable,
+    /// Le géocodeur n'a pas pu être interrogé : voir la colonne erreur
+    Erreur,
+}
+
+impl Statut {
+    fn nom(self) -> &'static str {
+        match self {
+            Statut::Valide => "valide",
+            Statut::Invalide => "invalide",
+            Statut::Imprecis => "imprecis",
+            Statut::Divergent => "divergent",
+            Statut::Introuvable => "introuvable",
+            Statut::Erreur => "erreur",
+        }
+    }
+}
+
+/// Niveau de qualité d'une adresse, écrit à côté de adresse_valide
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Qualite {
+    Valide,
+    AVerifier,
+    Invalide,
+    Erreur,
+}
+
+impl Qualite {
+    fn nom(self) -> &'static str {
+        match self {
+            Qualite::Valide => "valide",
+            Qualite::AVerifier => "a_verifier",
+            Qualite::Invalide => "invalide",
+            Qualite::Erreur => "erreur",
+        }
+    }
+}
+
+/// Bornes de score utilisées pour classer les adresses
+struct Seuils {
+    validite: f64,
+    revue: Option<f64>,
+}
+
+impl Seuils {
+    fn depuis_args(args: &Args) -> Result<Self, Box<dyn Error>> {
+        for seuil in [Some(args.threshold), args.review_threshold]
+            .into_iter()
+            .flatten()
+        {
+            if !(0.0..=1.0).contains(&seuil) {
+                return Err(format!("seuil hors de l'intervalle [0, 1] : {seuil}").into());
             }
         }
+        if args
+            .review_threshold
+            .is_some_and(|revue| revue > args.threshold)
+        {
+            return Err("--review-threshold doit être inférieur ou égal à --threshold".into());
+        }
+        Ok(Seuils {
+            validite: args.threshold,
+            revue: args.review_threshold,
+        })
+    }
+
+    /// Classe un score (absent si l'API n'a rien trouvé)
+    fn classer(&self, score: Option<f64>) -> Qualite {
+        match score {
+            Some(score) if score >= self.validite => Qualite::Valide,
+            Some(score) if self.revue.is_some_and(|revue| score >= revue) => Qualite::AVerifier,
+            _ => Qualite::Invalide,
+        }
     }
-    false
 }
 
-/// Génération du nom de sortie avec suffixe _chk
-fn generer_nom_sortie(input: &str) -> String {
+/// Règles appliquées à chaque ligne vérifiée
+struct Regles {
+    seuils: Seuils,
+    /// Base de La Poste (--postcode-file)
+    codes_postaux: Option<CodesPostaux>,
+    strict: bool,
+    granularite_minimale: Option<Granularite>,
+}
+
+impl Regles {
+    fn depuis_args(args: &Args) -> Result<Self, Box<dyn Error>> {
+        Ok(Regles {
+            seuils: Seuils::depuis_args(args)?,
+            codes_postaux: args
+                .postcode_file
+                .as_deref()
+                .map(CodesPostaux::charger)
+                .transpose()?,
+            strict: args.strict,
+            granularite_minimale: args.min_granularity,
+        })
+    }
+}
+
+/// Paris, Lyon ou Marseille, d'après le code postal d'un arrondissement
+/// (75116 dessert une partie du 16e arrondissement de Paris)
+fn plm_cp(cp: &str) -> Option<&'static str> {
+    if cp.len() != 5 {
+        return None;
+    }
+    let numero: u32 = cp.get(3..)?.parse().ok()?;
+    match (cp.get(..3)?, numero) {
+        ("750", 0..=20) | ("751", 16) => Some("PARIS"),
+        ("690", 0..=9) => Some("LYON"),
+        ("130", 0..=16) => Some("MARSEILLE"),
+        _ => None,
+    }
+}
+
+/// Paris, Lyon ou Marseille, d'après le code INSEE de la commune ou d'un arrondissement
+fn plm_insee(insee: &str) -> Option<&'static str> {
+    match insee {
+        "75056" => return Some("PARIS"),
+        "69123" => return Some("LYON"),
+        "13055" => return Some("MARSEILLE"),
+        _ => {}
+    }
+    if insee.len() != 5 {
+        return None;
+    }
+    let numero: u32 = insee.get(3..)?.parse().ok()?;
+    match (insee.get(..3)?, numero) {
+        ("751", 1..=20) => Some("PARIS"),
+        ("693", 81..=89) => Some("LYON"),
+        ("132", 1..=16) => Some("MARSEILLE"),
+        _ => None,
+    }
+}
+
+/// Vrai si le code postal trouvé correspond à celui de l'entrée : identique, même
+/// code INSEE, ou arrondissement de la même ville pour Paris, Lyon et Marseille
+fn meme_cp(cp: &str, postcode: &str, citycode: Option<&str>, insee: Option<&str>) -> bool {
+    let cp = cp.trim();
+    let plm = plm_cp(cp);
+    cp == postcode
+        || insee.is_some_and(|insee| citycode == Some(insee))
+        || (plm.is_some() && (plm_cp(postcode) == plm || citycode.and_then(plm_insee) == plm))
+}
+
+impl OutputRecord {
+    /// Construit la ligne de sortie en reprenant le meilleur résultat de l'API
+    fn nouveau(
+        input: InputRecord,
+        resultat: Result<Option<Correspondance>, ErreurGeocodage>,
+        seuils: &Seuils,
+    ) -> Self {
+        let qualite = match &resultat {
+            Ok(correspondance) => seuils.classer(correspondance.as_ref().map(|r| r.score)),
+            Err(_) => Qualite::Erreur,
+        };
+        let statut = match &resultat {
+            Ok(Some(_)) if qualite == Qualite::Valide => Statut::Valide,
+            Ok(Some(_)) => Statut::Invalide,
+            Ok(None) => Statut::Introuvable,
+            Err(_) => Statut::Erreur,
+        };
+        let requete = input.requete().texte();
+        let mut output = OutputRecord {
+            entree: input.ligne,
+            adresse_voie: input.adresse,
+            complement_adresse: input.complement,
+            requete,
+            extrait: input.extrait,
+            controle_cp: None,
+            divergence: None,
+            adresse_valide: qualite == Qualite::Valide,
+            qualite,
+            statut,
+            granularite: None,
+            erreur: resultat.as_ref().err().map(ToString::to_string),
+            ban_label: None,
+            ban_score: None,
+            ban_type: None,
+            ban_id: None,
+            ban_citycode: None,
+            ban_postcode: None,
+            ban_city: None,
+            ban_housenumber: None,
+            ban_street: None,
+            longitude: None,
+            latitude: None,
+        };
+        if let Ok(Some(r)) = resultat {
+            output.ban_label = Some(r.label);
+            output.ban_score = Some(r.score);
+            output.granularite = Granularite::du_type(&r.type_resultat);
+            output.ban_type = Some(r.type_resultat);
+            output.ban_id = Some(r.id);
+            output.ban_citycode = Some(r.citycode);
+            output.ban_postcode = r.postcode;
+            output.ban_city = Some(r.city);
+            output.ban_housenumber = r.housenumber;
+            output.ban_street = r.street;
+            output.longitude = Some(r.longitude);
+            output.latitude = Some(r.latitude);
+        }
+        output
+    }
+
+    /// Compare le code postal et la commune trouvés à ceux de l'entrée (--strict) ;
+    /// un composant absent de l'entrée n'est pas comparé
+    fn comparer(&mut self, cp: &str, ville: &str) {
+        let mut divergence = Vec::new();
+        let insee = self
+            .controle_cp
+            .as_ref()
+            .and_then(|controle| controle.insee.as_deref());
+        // Nominatim et Photon ne renvoient pas toujours le code postal : la commune
+        // est alors comparée seule
+        if let Some(postcode) = &self.ban_postcode
+            && !cp.trim().is_empty()
+            && !meme_cp(cp, postcode, self.ban_citycode.as_deref(), insee)
+        {
+            divergence.push("cp");
+        }
+        if let Some(city) = &self.ban_city
+            && !ville.trim().is_empty()
+            && nom_commune(ville) != nom_commune(city)
+        {
+            divergence.push("ville");
+        }
+        if !divergence.is_empty() {
+            self.declasser(Statut::Divergent);
+        }
+        self.divergence = Some(divergence);
+    }
+
+    /// Écarte un résultat moins précis que `minimum` (--min-granularity)
+    fn exiger(&mut self, minimum: Granularite) {
+        // Un type inconnu est compté comme le moins précis
+        let atteinte = self.granularite.unwrap_or(Granularite::Municipality);
+        if self.ban_type.is_some() && atteinte < minimum {
+            self.declasser(Statut::Imprecis);
+        }
+    }
+
+    /// Rend invalide une adresse trouvée ; le statut d'une adresse qui était
+    /// valide indique la règle qui l'a écartée
+    fn declasser(&mut self, statut: Statut) {
+        if self.statut == Statut::Valide {
+            self.statut = statut;
+        }
+        if self.qualite != Qualite::Erreur {
+            self.qualite = Qualite::Invalide;
+            self.adresse_valide = false;
+        }
+    }
+
+    /// Voie découpée d'après le résultat du géocodeur si l'adresse est valide,
+    /// d'après la ligne lue sinon ; le second élément indique la source
+    fn decoupage(&self) -> (voie::Decoupage, &'static str) {
+        match self.ban_street.as_deref().filter(|_| self.adresse_valide) {
+            Some(voie) => {
+                let numero = self.ban_housenumber.as_deref().unwrap_or_default();
+                (
+                    voie::decomposer_resultat(&format!("{numero} {voie}")),
+                    "geocodeur",
+                )
+            }
+            None => (voie::decomposer(&self.adresse_voie), "analyse"),
+        }
+    }
+
+    /// En-tête des colonnes ajoutées à celles de l'entrée
+    fn colonnes_validation(adresse_complete: bool, regles: &Regles) -> Vec<&'static str> {
+        let mut colonnes = vec![
+            "adresse_valide",
+            "qualite",
+            "statut",
+            "granularite",
+            "erreur",
+            "adresse_voie",
+            "complement_adresse",
+            "requete",
+            "voie_numero",
+            "voie_indice",
+            "voie_type",
+            "voie_nom",
+            "voie_source",
+        ];
+        if adresse_complete {
+            colonnes.extend(["cp_extrait", "ville_extraite"]);
+        }
+        if regles.codes_postaux.is_some() {
+            colonnes.extend(["cp_controle", "cp_suggere", "insee_commune"]);
+        }
+        if regles.strict {
+            colonnes.push("divergence");
+        }
+        colonnes.extend(Self::COLONNES_RESULTAT);
+        colonnes
+    }
+
+    /// Colonnes décrivant le meilleur résultat du géocodeur
+    const COLONNES_RESULTAT: [&str; 9] = [
+        "ban_label",
+        "ban_score",
+        "ban_type",
+        "ban_id",
+        "ban_citycode",
+        "ban_postcode",
+        "ban_city",
+        "longitude",
+        "latitude",
+    ];
+
+    /// Valeurs des colonnes de validation, vides quand elles sont absentes
+    fn validation(&self) -> Vec<String> {
+        let texte = |valeur: &Option<String>| valeur.clone().unwrap_or_default();
+        let nombre = |valeur: Option<f64>| valeur.map(|v| v.to_string()).unwrap_or_default();
+        let mut valeurs = vec![
+            self.adresse_valide.to_string(),
+            self.qualite.nom().to_string(),
+            self.statut.nom().to_string(),
+            self.granularite
+                .map(Granularite::nom)
+                .unwrap_or_default()
+                .to_string(),
+            texte(&self.erreur),
+            self.adresse_voie.clone(),
+            self.complement_adresse.clone(),
+            self.requete.clone(),
+        ];
+        let (decoupage, source) = self.decoupage();
+        valeurs.extend([
+            decoupage.numero,
+            decoupage.indice,
+            decoupage.type_voie,
+            decoupage.nom,
+            source.to_string(),
+        ]);
+        if let Some((cp, ville)) = &self.extrait {
+            valeurs.extend([cp.clone(), ville.clone()]);
+        }
+        if let Some(controle) = &self.controle_cp {
+            valeurs.extend([
+                controle.etat.nom().to_string(),
+                controle.suggestion.join(" "),
+                texte(&controle.insee),
+            ]);
+        }
+        if let Some(divergence) = &self.divergence {
+            valeurs.push(divergence.join("+"));
+        }
+        valeurs.extend([
+            texte(&self.ban_label),
+            nombre(self.ban_score),
+            texte(&self.ban_type),
+            texte(&self.ban_id),
+            texte(&self.ban_citycode),
+            texte(&self.ban_postcode),
+            texte(&self.ban_city),
+            nombre(self.longitude),
+            nombre(self.latitude),
+        ]);
+        valeurs
+    }
+
+    /// Ligne écrite : colonnes d'entrée, colonnes de validation insérées à `position`
+    fn ligne(&self, position: usize) -> StringRecord {
+        inserer(&self.entree, self.validation(), position)
+    }
+}
+
+/// Insère des colonnes dans une ligne, à `position` ou à la fin si elle est plus courte
+fn inserer<T: AsRef<str>>(
+    ligne: &StringRecord,
+    colonnes: impl IntoIterator<Item = T>,
+    position: usize,
+) -> StringRecord {
+    let position = position.min(ligne.len());
+    let mut resultat: StringRecord = ligne.iter().take(position).collect();
+    for colonne in colonnes {
+        resultat.push_field(colonne.as_ref());
+    }
+    for champ in ligne.iter().skip(position) {
+        resultat.push_field(champ);
+    }
+    resultat
+}
+
+/// Vérifie un lot de lignes, d'un seul appel si `batch` ou adresse par adresse sinon
+fn verifier_lot(
+    geocodeur: &dyn Geocodeur,
+    regles: &Regles,
+    lot: Vec<InputRecord>,
+    batch: bool,
+) -> Vec<OutputRecord> {
+    let resultats: Vec<_> = if batch {
+        let requetes: Vec<Requete> = lot.iter().map(InputRecord::requete).collect();
+        match geocodeur.geocoder_lot(&requetes) {
+            Ok(resultats) => resultats.into_iter().map(Ok).collect(),
+            // Le lot entier n'a pas pu être vérifié
+            Err(erreur) => vec![Err(erreur); lot.len()],
+        }
+    } else {
+        lot.iter()
+            .map(|input| {
+                geocodeur
+                    .geocoder(&input.requete(), 1)
+                    .map(|candidats| candidats.into_iter().next())
+            })
+            .collect()
+    };
+    lot.into_iter()
+        .zip(resultats)
+        .map(|(input, resultat)| {
+            let controle_cp = regles
+                .codes_postaux
+                .as_ref()
+                .map(|base| base.controler(&input.cp, &input.ville));
+            let (cp, ville) = (input.cp.clone(), input.ville.clone());
+            let mut output = OutputRecord::nouveau(input, resultat, &regles.seuils);
+            output.controle_cp = controle_cp;
+            if let Some(minimum) = regles.granularite_minimale {
+                output.exiger(minimum);
+            }
+            if regles.strict {
+                output.comparer(&cp, &ville);
+            }
+            output
+        })
+        .collect()
+}
+
+/// Génération du nom de sortie avec suffixe _chk et l'extension du format demandé
+fn generer_nom_sortie(input: &str, format: Option<Format>) -> String {
     let path = Path::new(input);
     let stem = path.file_stem().unwrap().to_string_lossy();
     let extension = path
@@ -76,28 +917,400 @@ fn generer_nom_sortie(input: &str) -> String {
         .unwrap_or_default();
     let parent = path.parent().unwrap_or_else(|| Path::new(""));
 
+    // Sans --format, un classeur est réécrit au format .xlsx
+    let extension = match format {
+        Some(format) => format.extension().into(),
+        None if tableur::est_classeur(path) => Format::Xlsx.extension().into(),
+        None => extension,
+    };
     let output_filename = format!("{}_chk.{}", stem, extension);
     parent.join(output_filename).to_string_lossy().to_string()
 }
 
-/// Fonction principale
-fn main() -> Result<(), Box<dyn Error>> {
+/// Fonction principale : code de sortie 2 si des lignes n'ont pas pu être vérifiées
+fn main() -> Result<ExitCode, Box<dyn Error>> {
     let args = Args::parse();
+    match &args.commande {
+        Some(Commande::Index {
+            action: ActionIndex::Build { exports, index_dir },
+        }) => {
+            let total = index_local::construire(exports, index_dir)?;
+            println!("✅ {total} adresses indexées dans {}", index_dir.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Commande::Cache {
+            action: ActionCache::Stats { options },
+        }) => {
+            let statistiques = options.ouvrir()?.statistiques(&options.cache_file)?;
+            println!(
+                "{} ({} Ko)",
+                options.cache_file.display(),
+                statistiques.taille_fichier / 1024
+            );
+            for (source, valides, expirees) in statistiques.par_source {
+                println!("  {source} : {valides} réponses valides, {expirees} expirées");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Commande::Cache {
+            action: ActionCache::Purge { all, options },
+        }) => {
+            let supprimees = options.ouvrir()?.purger(*all)?;
+            println!("✅ {supprimees} réponses supprimées du cache");
+            Ok(ExitCode::SUCCESS)
+        }
+        None => verifier_fichier(&args),
+    }
+}
+
+/// Lignes du fichier d'entrée, première ligne comprise qu'elle soit un en-tête ou non
+struct Entree {
+    premiere_ligne: StringRecord,
+    /// Lignes à vérifier, sans l'en-tête
+    lignes: Box<dyn Iterator<Item = csv::Result<StringRecord>> + Send>,
+    /// Feuille lue, pour un classeur
+    feuille: Option<String>,
+    /// Forme du fichier texte et présence d'un BOM, reprises en sortie
+    forme: Option<(dialecte::Dialecte, bool)>,
+}
+
+/// Ouvre l'entrée : base SQLite, classeur d'après son extension, texte délimité sinon
+fn lire_entree(
+    args: &Args,
+    chemin: &Path,
+    base: Option<&SourceSqlite>,
+) -> Result<Entree, Box<dyn Error>> {
+    if let Some(base) = base {
+        // Les noms des colonnes de la table ou de la requête tiennent lieu d'en-tête
+        let (entete, lignes) = base.lire()?;
+        return Ok(Entree {
+            premiere_ligne: entete,
+            lignes: Box::new(lignes.into_iter().map(Ok)),
+            feuille: None,
+            forme: None,
+        });
+    }
+    if tableur::est_classeur(chemin) {
+        let feuille = tableur::lire(chemin, args.sheet.as_deref())?;
+        let mut lignes = feuille.lignes.into_iter().map(Ok).peekable();
+        let premiere_ligne = match lignes.peek() {
+            Some(Ok(ligne)) => ligne.clone(),
+            _ => return Err(format!("la feuille « {} » est vide", feuille.nom).into()),
+        };
+        if !args.no_header {
+            lignes.next();
+        }
+        return Ok(Entree {
+            premiere_ligne,
+            lignes: Box::new(lignes),
+            feuille: Some(feuille.nom),
+            forme: None,
+        });
+    }
+
+    // Le fichier est décodé en entier avant l'analyse CSV
+    let octets = fs::read(chemin)?;
+    let impose = args
+        .encoding
+        .as_deref()
+        .map(encodage::par_nom)
+        .transpose()?;
+    let decode = encodage::decoder(&octets, impose);
+    if decode.encodage != encoding_rs::UTF_8 {
+        eprintln!("ℹ Encodage d'entrée : {}", decode.encodage.name());
+    }
+    if decode.avec_erreurs {
+        eprintln!(
+            "⚠ Caractères invalides en {} remplacés par « \u{FFFD} » : vérifier --encoding",
+            decode.encodage.name()
+        );
+    }
+    let entree = dialecte::Dialecte {
+        separateur: args
+            .delimiter
+            .unwrap_or_else(|| dialecte::detecter_separateur(&decode.texte, args.quote)),
+        guillemet: args.quote,
+        echappement: args.escape,
+        crlf: dialecte::fins_de_ligne_crlf(&decode.texte),
+    };
+    if args.delimiter.is_none() {
+        eprintln!(
+            "ℹ Séparateur détecté : {}",
+            dialecte::nom_separateur(entree.separateur)
+        );
+    }
+    let mut rdr = entree
+        .lecteur()
+        .has_headers(!args.no_header)
+        .from_reader(Cursor::new(decode.texte.into_bytes()));
+    // Sans en-tête, headers() renvoie la première ligne sans la consommer
+    let premiere_ligne = rdr.headers()?.clone();
+    Ok(Entree {
+        premiere_ligne,
+        lignes: Box::new(rdr.into_records()),
+        feuille: None,
+        forme: Some((entree, decode.bom)),
+    })
+}
+
+/// Délai entre deux enregistrements du point de reprise
+const INTERVALLE_REPRISE: Duration = Duration::from_secs(2);
+
+/// Vérifie les adresses du fichier d'entrée et écrit le fichier _chk
+fn verifier_fichier(args: &Args) -> Result<ExitCode, Box<dyn Error>> {
+    // Présents grâce à `required = true` quand aucune sous-commande n'est donnée
+    let input_file = args.input_file.as_deref().unwrap();
+    let lines_to_check = args.lines_to_check.unwrap();
+
+    let regles = Regles::depuis_args(args)?;
+    // L'index local est assez rapide pour se passer du cache disque
+    let disque = if args.no_cache || args.backend == Backend::Local {
+        None
+    } else {
+        Some(args.cache.ouvrir()?)
+    };
+    let source = format!(
+        "{}|{}",
+        args.backend.to_possible_value().unwrap().get_name(),
+        args.api_url.as_deref().unwrap_or_default()
+    );
+    let geocodeur = GeocodeurEnCache::new(args.backend.geocodeur(args)?, source, disque);
 
-    let mut rdr = ReaderBuilder::new()
-        .delimiter(b'\t')
-        .has_headers(true)
-        .from_path(&args.input_file)?;
+    let base = SourceSqlite::analyser(input_file).transpose()?;
+    let chemin_entree = base
+        .as_ref()
+        .map_or(Path::new(input_file), |base| base.chemin.as_path());
+    // Sans --format, les résultats d'une base SQLite y sont écrits
+    let base_sortie = base.as_ref().filter(|_| args.format.is_none());
+    if base_sortie.is_none() && (args.output_table.is_some() || args.update_key.is_some()) {
+        return Err(
+            "--output-table et --update-key demandent une entrée sqlite:… sans --format".into(),
+        );
+    }
+    let Entree {
+        premiere_ligne,
+        lignes,
+        feuille,
+        forme,
+    } = lire_entree(args, chemin_entree, base.as_ref())?;
+    let entete = (!args.no_header).then_some(&premiere_ligne);
+    let mut designations = match &args.columns_file {
+        Some(chemin) => colonnes::lire_fichier(chemin)?,
+        None => Vec::new(),
+    };
+    // --column l'emporte sur le fichier de correspondance
+    designations.extend(args.column.iter().cloned());
+    let colonnes = Colonnes::reperer(
+        entete,
+        premiere_ligne.len(),
+        &designations,
+        args.full_address,
+    )?;
+    if let Some(resume) = entete.and_then(|entete| colonnes.resume(entete)) {
+        eprintln!("ℹ Colonnes : {resume}");
+    }
+    let position_validation =
+        colonnes::position_insertion(entete, premiere_ligne.len(), args.insert_after.as_deref())?;
+    // Toutes les colonnes d'entrée sont recopiées ; sans en-tête, elles sont numérotées
+    let colonnes_validation = OutputRecord::colonnes_validation(args.full_address, &regles);
+    let nombre_validation = colonnes_validation.len();
+    let entete_sortie = inserer(
+        &entete.cloned().unwrap_or_else(|| {
+            (1..=premiere_ligne.len())
+                .map(|numero| format!("colonne_{numero}"))
+                .collect()
+        }),
+        colonnes_validation,
+        position_validation,
+    );
+
+    let output_path = generer_nom_sortie(&chemin_entree.to_string_lossy(), args.format);
+    let chemin_reprise = reprise::chemin(&output_path);
+    let empreinte_entree = reprise::empreinte(chemin_entree)?;
+    // Colonnes et options qui déterminent l'écriture des lignes
+    let forme_sortie = reprise::empreinte_texte(&format!(
+        "{}\n{:?} {:?} {:?} {:?} {:?} {} {} {}",
+        entete_sortie.iter().collect::<Vec<_>>().join("\t"),
+        args.format.map(|f| f as u8),
+        args.delimiter,
+        args.output_delimiter,
+        args.encoding,
+        args.output_encoding,
+        args.quote,
+        args.escape as u8,
+        args.unmappable as u8,
+    ));
+    let point = if args.resume {
+        reprise::lire(&chemin_reprise)?
+    } else {
+        None
+    };
+    // Sans --format, la sortie a la forme de l'entrée
+    let format = args
+        .format
+        .or((forme.is_none() && base.is_none()).then_some(Format::Xlsx));
+    if format == Some(Format::Xlsx) {
+        // Un classeur n'est écrit qu'à la fin et ne peut pas être repris
+        if args.resume {
+            return Err("--resume n'est pas disponible pour une sortie .xlsx".into());
+        }
+    } else if base_sortie.is_some() {
+        if args.resume {
+            return Err("--resume n'est pas disponible pour une sortie SQLite".into());
+        }
+        if args.output_encoding.is_some() {
+            return Err("--output-encoding ne s'applique qu'aux sorties csv et tsv".into());
+        }
+    } else if args.output_encoding.is_some()
+        && format.is_some_and(|f| f != Format::Csv && f != Format::Tsv)
+    {
+        return Err("--output-encoding ne s'applique qu'aux sorties csv et tsv".into());
+    }
+
+    let (deja_traitees, fichier) = match &point {
+        _ if format == Some(Format::Xlsx) || base_sortie.is_some() => (0, None),
+        Some(point) if point.empreinte_entree != empreinte_entree => {
+            return Err(format!(
+                "{input_file} a changé depuis l'interruption : relancer sans --resume"
+            )
+            .into());
+        }
+        Some(point) if point.forme_sortie != forme_sortie => {
+            return Err(
+                "colonnes ou options de sortie différentes de l'interruption : relancer sans --resume"
+                    .into(),
+            );
+        }
+        Some(point) => {
+            // Ce qui a été écrit après le dernier point de reprise est repris
+            let mut fichier = OpenOptions::new().write(true).open(&output_path)?;
+            fichier.set_len(point.octets_ecrits)?;
+            fichier.seek(SeekFrom::End(0))?;
+            (point.lignes_traitees, Some(fichier))
+        }
+        None => {
+            if args.resume {
+                eprintln!("⚠ Aucun point de reprise pour {output_path} : vérification complète");
+            }
+            // La sortie est recréée : un ancien point de reprise ne lui correspond plus
+            if chemin_reprise.exists() {
+                fs::remove_file(&chemin_reprise)?;
+            }
+            (0, Some(File::create(&output_path)?))
+        }
+    };
+    // Ce que contient le résultat, pour les messages de fin
+    let mut cible = output_path.clone();
+    let mut sortie: Box<dyn Sortie> = match (format, fichier) {
+        _ if let Some(base) = base_sortie => {
+            let base_nom = base.chemin.display();
+            match &args.update_key {
+                Some(cle) => {
+                    let table = base.table.as_deref().ok_or(
+                        "--update-key demande une entrée sqlite:…?table=… plutôt qu'une requête",
+                    )?;
+                    // La clé est cherchée parmi les colonnes lues, avant insertion de la validation
+                    let position_cle = premiere_ligne
+                        .iter()
+                        .position(|colonne| colonne.eq_ignore_ascii_case(cle))
+                        .ok_or_else(|| format!("colonne « {cle} » introuvable dans {table}"))?;
+                    let position_cle = if position_cle < position_validation {
+                        position_cle
+                    } else {
+                        position_cle + nombre_validation
+                    };
+                    cible = format!("table {table} de {base_nom}");
+                    Box::new(SortieMiseAJour::new(
+                        &base.chemin,
+                        table,
+                        &entete_sortie,
+                        position_cle,
+                        position_validation..position_validation + nombre_validation,
+                    )?)
+                }
+                None => {
+                    let table = args.output_table.clone().unwrap_or_else(|| {
+                        format!("{}_chk", base.table.as_deref().unwrap_or("requete"))
+                    });
+                    if base
+                        .table
+                        .as_deref()
+                        .is_some_and(|source| source.eq_ignore_ascii_case(&table))
+                    {
+                        return Err(format!(
+                            "--output-table {table} remplacerait la table lue : utiliser --update-key pour la compléter"
+                        )
+                        .into());
+                    }
+                    cible = format!("table {table} de {base_nom}");
+                    Box::new(SortieTable::new(
+                        &base.chemin,
+                        &table,
+                        &entete_sortie,
+                        position_validation..position_validation + nombre_validation,
+                        &base.types()?,
+                        args.replace_table || args.output_table.is_none(),
+                    )?)
+                }
+            }
+        }
+        (Some(Format::Jsonl), Some(fichier)) => Box::new(SortieJsonl::new(fichier, &entete_sortie)),
+        (Some(Format::Geojson), Some(fichier)) => {
+            Box::new(SortieGeoJson::new(fichier, &entete_sortie)?)
+        }
+        (Some(Format::Xlsx), _) | (_, None) => {
+            let feuille = feuille.as_deref().unwrap_or("Feuil1");
+            Box::new(ClasseurSortie::new(
+                Path::new(&output_path),
+                feuille,
+                &entete_sortie,
+            )?)
+        }
+        (_, Some(fichier)) => {
+            // La sortie reprend la forme de l'entrée : séparateur, fins de ligne et BOM ;
+            // une entrée classeur convertie en texte prend la forme CSV usuelle
+            let (mut forme_sortie, bom) = forme.unwrap_or((
+                dialecte::Dialecte {
+                    separateur: b',',
+                    guillemet: args.quote,
+                    echappement: args.escape,
+                    crlf: false,
+                },
+                false,
+            ));
+            match format {
+                Some(Format::Csv) => forme_sortie.separateur = b',',
+                Some(Format::Tsv) => forme_sortie.separateur = b'\t',
+                _ => {}
+            }
+            if let Some(separateur) = args.output_delimiter {
+                forme_sortie.separateur = separateur;
+            }
+            let encodage_sortie = match &args.output_encoding {
+                Some(nom) => encodage::sortie_par_nom(nom)?,
+                None => encoding_rs::UTF_8,
+            };
+            Box::new(SortieCsv::new(
+                fichier,
+                &entete_sortie,
+                forme_sortie,
+                encodage_sortie,
+                args.unmappable,
+                bom,
+            )?)
+        }
+    };
 
-    let output_path = generer_nom_sortie(&args.input_file);
-    let mut wtr = WriterBuilder::new()
-        .delimiter(b'\t')
-        .has_headers(true)
-        .from_path(&output_path)?;
+    // Ctrl-C : on arrête de lire, on termine les lots en cours puis on enregistre
+    // le point de reprise
+    let interrompu = Arc::new(AtomicBool::new(false));
+    {
+        let interrompu = Arc::clone(&interrompu);
+        ctrlc::set_handler(move || interrompu.store(true, Ordering::SeqCst))?;
+    }
 
-    // Écrire l’en-tête avec le champ supplémentaire
-    wtr.write_record(&["nom", "adresse", "cp", "ville", "contact", "adresse_valide"])?;
-    let pb = ProgressBar::new(args.lines_to_check as u64);
+    let pb = ProgressBar::new(lines_to_check as u64);
+    pb.set_position(deja_traitees);
     pb.set_style(
         ProgressStyle::with_template(
             "[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} lignes ({eta})",
@@ -105,30 +1318,161 @@ fn main() -> Result<(), Box<dyn Error>> {
         .unwrap(),
     );
 
-    for (i, result) in rdr.deserialize::<InputRecord>().enumerate() {
-        let input = result?;
+    // En mode unitaire, un lot = une ligne
+    let taille_lot = if args.batch {
+        args.batch_size as usize
+    } else {
+        1
+    };
+    let mut entrees = lignes
+        .map(|ligne| {
+            ligne.map(|ligne| {
+                InputRecord::depuis(ligne, &colonnes, args.full_address, !args.no_normalize)
+            })
+        })
+        .take(lines_to_check)
+        .skip(deja_traitees as usize);
 
-        if i >= args.lines_to_check {
-            break;
+    let (tx_lots, rx_lots) =
+        mpsc::sync_channel::<(usize, Vec<InputRecord>)>(args.workers as usize * 2);
+    let rx_lots = Arc::new(Mutex::new(rx_lots));
+    let (tx_sorties, rx_sorties) = mpsc::channel::<(usize, Vec<OutputRecord>)>();
+
+    let mut en_erreur = 0;
+    let mut lignes_traitees = deja_traitees;
+    // Renvoie faux si la sortie ne peut pas être reprise
+    let enregistrer_reprise =
+        |sortie: &mut dyn Sortie, lignes_traitees| -> Result<bool, Box<dyn Error>> {
+            let Some(octets_ecrits) = sortie.point_de_reprise()? else {
+                return Ok(false);
+            };
+            let point = reprise::PointDeReprise {
+                empreinte_entree: empreinte_entree.clone(),
+                forme_sortie: forme_sortie.clone(),
+                lignes_traitees,
+                octets_ecrits,
+            };
+            reprise::enregistrer(&chemin_reprise, &point)?;
+            Ok(true)
+        };
+
+    thread::scope(|s| -> Result<(), Box<dyn Error>> {
+        // Lecture : les lots sont numérotés pour être réécrits dans l'ordre
+        let interrompu = &interrompu;
+        let lecteur = s.spawn(move || -> csv::Result<()> {
+            for numero in 0.. {
+                if interrompu.load(Ordering::SeqCst) {
+                    break;
+                }
+                let lot = entrees
+                    .by_ref()
+                    .take(taille_lot)
+                    .collect::<csv::Result<Vec<_>>>()?;
+                if lot.is_empty() || tx_lots.send((numero, lot)).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+
+        for _ in 0..args.workers {
+            let rx_lots = Arc::clone(&rx_lots);
+            let tx_sorties = tx_sorties.clone();
+            let geocodeur = &geocodeur;
+            let regles = &regles;
+            let batch = args.batch;
+            s.spawn(move || {
+                loop {
+                    // Le verrou est relâché avant la vérification du lot
+                    let lot = rx_lots.lock().unwrap().recv();
+                    let Ok((numero, lot)) = lot else { break };
+                    let sorties = verifier_lot(geocodeur, regles, lot, batch);
+                    if tx_sorties.send((numero, sorties)).is_err() {
+                        break;
+                    }
+                }
+            });
         }
+        drop(rx_lots);
+        drop(tx_sorties);
 
-        let ok = verifier_adresse_api(&input.adresse, &input.cp, &input.ville);
-        std::thread::sleep(std::time::Duration::from_millis(33));
+        // Écriture dans l'ordre d'entrée : les lots terminés en avance attendent leur tour
+        let mut en_attente = BTreeMap::new();
+        let mut suivant = 0;
+        let mut dernier_enregistrement = Instant::now();
+        for (numero, sorties) in rx_sorties {
+            pb.inc(sorties.len() as u64);
+            en_erreur += sorties
+                .iter()
+                .filter(|sortie| sortie.statut == Statut::Erreur)
+                .count();
+            en_attente.insert(numero, sorties);
+            while let Some(lignes) = en_attente.remove(&suivant) {
+                for verifiee in lignes {
+                    lignes_traitees += 1;
+                    // Avec --unmappable error, la ligne fautive est signalée
+                    sortie
+                        .ecrire(&verifiee.ligne(position_validation))
+                        .map_err(|erreur| format!("ligne {lignes_traitees} : {erreur}"))?;
+                }
+                suivant += 1;
+            }
+            if dernier_enregistrement.elapsed() >= INTERVALLE_REPRISE {
+                enregistrer_reprise(sortie.as_mut(), lignes_traitees)?;
+                dernier_enregistrement = Instant::now();
+            }
+        }
+        lecteur.join().unwrap()?;
+        Ok(())
+    })?;
 
-        let output = OutputRecord {
-            nom: input.nom,
-            adresse: input.adresse,
-            cp: input.cp,
-            ville: input.ville,
-            contact: input.contact,
-            adresse_valide: ok,
-        };
+    if interrompu.load(Ordering::SeqCst) {
+        let reprenable = enregistrer_reprise(sortie.as_mut(), lignes_traitees)?;
+        sortie.terminer()?;
+        pb.abandon_with_message("Interrompu");
+        if reprenable {
+            eprintln!(
+                "⏸ Interrompu après {lignes_traitees} lignes : relancer avec --resume pour continuer"
+            );
+        } else {
+            eprintln!(
+                "⏸ Interrompu après {lignes_traitees} lignes : {cible} ne contient que celles-ci"
+            );
+        }
+        return Ok(ExitCode::from(130));
+    }
+
+    pb.finish_with_message("✔ Vérification terminée !");
+    let avertissement = sortie.terminer()?;
+    // Vérification complète : plus rien à reprendre
+    if chemin_reprise.exists() {
+        fs::remove_file(&chemin_reprise)?;
+    }
+    if base_sortie.is_some() {
+        println!("✅ Résultats écrits : {cible}");
+    } else {
+        println!("✅ Fichier généré : {output_path}");
+    }
+    if let Some(avertissement) = avertissement {
+        eprintln!("⚠ {avertissement}");
+    }
+    if en_erreur > 0 {
+        eprintln!("⚠ {en_erreur} lignes n'ont pas pu être vérifiées (statut « erreur »)");
+        return Ok(ExitCode::from(2));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        wtr.serialize(output)?;
-        pb.inc(1);
+    #[test]
+    fn arrondissements_toleres() {
+        assert!(meme_cp("75016", "75116", Some("75116"), None));
+        assert!(meme_cp("69000", "69003", Some("69383"), None));
+        assert!(meme_cp("13001", "13008", None, None));
+        assert!(!meme_cp("69000", "69100", Some("69266"), None));
+        assert!(!meme_cp("75016", "92100", Some("92012"), None));
     }
-pb.finish_with_message("✔ Vérification terminée !");
-    wtr.flush()?;
-    println!("✅ Fichier généré : {}", output_path);
-    Ok(())
 }
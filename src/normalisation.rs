@@ -0,0 +1,237 @@
+//! Normalisation de l'adresse avant l'envoi au géocodeur
+//!
+//! « BD ST GERMAIN », « av. du gal de gaulle » ou « 12B R DES LILAS » font
+//! baisser le score : les abréviations usuelles sont développées, la
+//! ponctuation et les caractères de contrôle retirés, les espaces réduits.
+
+use crate::texte::sans_accents;
+
+/// Abréviations de types de voie, développées en tête de ligne (après le numéro) ;
+/// les types développés figurent dans `voie::TYPES_VOIE`
+pub const ABREVIATIONS_VOIE: &[(&str, &str)] = &[
+    ("R", "rue"),
+    ("AV", "avenue"),
+    ("AVE", "avenue"),
+    ("BD", "boulevard"),
+    ("BLD", "boulevard"),
+    ("BLVD", "boulevard"),
+    ("CHE", "chemin"),
+    ("CH", "chemin"),
+    ("CHEM", "chemin"),
+    ("IMP", "impasse"),
+    ("ALL", "allée"),
+    ("PL", "place"),
+    ("RTE", "route"),
+    ("CRS", "cours"),
+    ("FBG", "faubourg"),
+    ("SQ", "square"),
+    ("PROM", "promenade"),
+    ("PAS", "passage"),
+    ("PASS", "passage"),
+    ("LOT", "lotissement"),
+    ("HAM", "hameau"),
+    ("RPT", "rond-point"),
+    ("RDPT", "rond-point"),
+    ("CHS", "chaussée"),
+    ("ESPL", "esplanade"),
+    ("SENT", "sentier"),
+    ("TRAV", "traverse"),
+    ("VLA", "villa"),
+    ("DOM", "domaine"),
+];
+
+/// Abréviations de titres, développées devant un nom propre ; celles qui sont
+/// aussi des mots courants (« col », « prés ») n'y figurent pas
+const TITRES: &[(&str, &str)] = &[
+    ("GAL", "général"),
+    ("GEN", "général"),
+    ("ST", "saint"),
+    ("STE", "sainte"),
+    ("MAL", "maréchal"),
+    ("PDT", "président"),
+    ("DR", "docteur"),
+    ("CDT", "commandant"),
+    ("CMDT", "commandant"),
+    ("LT", "lieutenant"),
+    ("CNE", "capitaine"),
+    ("MGR", "monseigneur"),
+];
+
+/// Mots après lesquels un titre est attendu : « rue du Gal… », « place de la Ste… »
+const ARTICLES: &[&str] = &["DU", "DE", "DES", "D", "LA", "LE", "L", "AU", "AUX"];
+
+/// Indices de répétition après un numéro
+const INDICES: &[(&str, &str)] = &[
+    ("B", "bis"),
+    ("BIS", "bis"),
+    ("T", "ter"),
+    ("TER", "ter"),
+    ("Q", "quater"),
+    ("QUATER", "quater"),
+];
+
+/// Développement d'un mot d'après une table, dans la casse du mot abrégé
+fn developper(mot: &str, table: &[(&str, &str)]) -> Option<String> {
+    let cle = sans_accents(mot).to_uppercase();
+    let (_, complet) = table.iter().find(|(abrege, _)| *abrege == cle)?;
+    let mut lettres = mot.chars();
+    let premiere = lettres.next()?;
+    Some(
+        if premiere.is_uppercase() && lettres.all(|c| !c.is_lowercase()) {
+            complet.to_uppercase()
+        } else if premiere.is_uppercase() {
+            let mut complet = complet.chars();
+            complet
+                .next()
+                .map(|c| c.to_uppercase().chain(complet).collect())
+                .unwrap_or_default()
+        } else {
+            complet.to_string()
+        },
+    )
+}
+
+/// Titres développés là où un titre est vraisemblable : suivi d'un nom, et en
+/// tête du nom, après un article ou dans un nom composé (« Pont-St-Esprit »)
+fn titres(mots: &[&str]) -> Vec<String> {
+    mots.iter()
+        .enumerate()
+        .map(|(i, mot)| {
+            let parties: Vec<&str> = mot.split('-').collect();
+            parties
+                .iter()
+                .enumerate()
+                .map(|(p, partie)| {
+                    let suivi = p + 1 < parties.len() || i + 1 < mots.len();
+                    let place = p > 0
+                        || i == 0
+                        || ARTICLES.contains(&sans_accents(mots[i - 1]).to_uppercase().as_str());
+                    let developpe = if suivi && place {
+                        developper(partie, TITRES)
+                    } else {
+                        None
+                    };
+                    developpe.unwrap_or_else(|| partie.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Mots du texte, sans ponctuation ni caractères de contrôle ; l'apostrophe
+/// et le trait d'union, qui font partie des noms, sont conservés
+fn mots(texte: &str) -> Vec<&str> {
+    texte
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '\'' | '’' | '-')))
+        .map(|mot| mot.trim_matches(|c: char| matches!(c, '\'' | '’' | '-')))
+        .filter(|mot| !mot.is_empty())
+        .collect()
+}
+
+/// Ligne de voie normalisée : « 12B R DES LILAS » devient « 12 BIS RUE DES LILAS »
+pub fn normaliser_voie(texte: &str) -> String {
+    normaliser_ligne(texte, true)
+}
+
+/// Voie déjà écrite en entier, comme celle renvoyée par le géocodeur : numéro et
+/// indice séparés, ponctuation retirée, mots conservés (« Rue du Col » le reste)
+pub fn normaliser_voie_complete(texte: &str) -> String {
+    normaliser_ligne(texte, false)
+}
+
+/// Numéro et indice séparés ; type de voie et titres développés si `abreviations`
+fn normaliser_ligne(texte: &str, abreviations: bool) -> String {
+    let mut resultat: Vec<String> = Vec::new();
+    let mut mots = mots(texte).into_iter().peekable();
+
+    // Numéro et indice de répétition, collé (« 12B ») ou séparé (« 12 B »)
+    if let Some(premier) = mots.next_if(|mot| mot.starts_with(|c: char| c.is_ascii_digit())) {
+        let fin = premier
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(premier.len());
+        let (numero, suite) = premier.split_at(fin);
+        if suite.is_empty() {
+            resultat.push(numero.to_string());
+            if let Some(indice) = mots.peek().and_then(|mot| developper(mot, INDICES)) {
+                resultat.push(indice);
+                mots.next();
+            }
+        } else if suite.chars().all(char::is_alphabetic) {
+            resultat.push(numero.to_string());
+            resultat.push(developper(suite, INDICES).unwrap_or_else(|| suite.to_string()));
+        } else {
+            // Plage « 12-14 » gardée telle quelle
+            resultat.push(premier.to_string());
+        }
+    }
+    if !abreviations {
+        resultat.extend(mots.map(str::to_string));
+        return resultat.join(" ");
+    }
+    if let Some(type_voie) = mots
+        .peek()
+        .and_then(|mot| developper(mot, ABREVIATIONS_VOIE))
+    {
+        resultat.push(type_voie);
+        mots.next();
+    }
+    resultat.extend(titres(&mots.collect::<Vec<_>>()));
+    resultat.join(" ")
+}
+
+/// Nom de commune normalisé : ponctuation retirée, « St »/« Ste » développés
+pub fn normaliser_commune(texte: &str) -> String {
+    titres(&mots(texte)).join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abreviations_developpees() {
+        assert_eq!(normaliser_voie("BD ST GERMAIN"), "BOULEVARD SAINT GERMAIN");
+        assert_eq!(
+            normaliser_voie("av. du gal de gaulle"),
+            "avenue du général de gaulle"
+        );
+        assert_eq!(normaliser_voie("12B R DES LILAS"), "12 BIS RUE DES LILAS");
+        assert_eq!(
+            normaliser_voie("3 t, Imp. du Dr Roux"),
+            "3 ter Impasse du Docteur Roux"
+        );
+        assert_eq!(
+            normaliser_voie("12-14 Av. Ste-Anne"),
+            "12-14 Avenue Sainte-Anne"
+        );
+        assert_eq!(normaliser_voie("rue du Mal Foch"), "rue du Maréchal Foch");
+        assert_eq!(
+            normaliser_commune("St-Amand-les-Eaux"),
+            "Saint-Amand-les-Eaux"
+        );
+    }
+
+    #[test]
+    fn mots_courants_conserves() {
+        assert_eq!(
+            normaliser_voie("3 route du Col de Vence"),
+            "3 route du Col de Vence"
+        );
+        assert_eq!(normaliser_commune("Le Col"), "Le Col");
+        assert_eq!(normaliser_voie("chemin des Prés"), "chemin des Prés");
+        // Un titre en fin de ligne n'est suivi d'aucun nom
+        assert_eq!(normaliser_voie("rue du Dr"), "rue du Dr");
+        assert_eq!(normaliser_voie("chemin le mal"), "chemin le mal");
+        assert_eq!(normaliser_commune("Mal"), "Mal");
+    }
+
+    #[test]
+    fn ponctuation_et_espaces() {
+        assert_eq!(
+            normaliser_voie("3 bis  rue\t(du) \"Dr\" Roux."),
+            "3 bis rue du Docteur Roux"
+        );
+        assert_eq!(normaliser_voie("rue\u{7}  de la Paix"), "rue de la Paix");
+    }
+}
@@ -0,0 +1,264 @@
+//! Géocodeurs basés sur OpenStreetMap : Nominatim et Photon
+//!
+//! Aucun des deux ne renvoie de score de correspondance : il est calculé en
+//! comparant l'adresse demandée au libellé du candidat.
+
+use crate::geocodeur::{Correspondance, ErreurGeocodage, Geocodeur, Requete, url_de_base};
+use crate::reseau::ClientHttp;
+use crate::texte::normaliser;
+use reqwest::Url;
+use serde::Deserialize;
+
+pub const URL_NOMINATIM: &str = "https://nominatim.openstreetmap.org";
+pub const URL_PHOTON: &str = "https://photon.komoot.io";
+
+/// Politique d'usage de nominatim.openstreetmap.org : une requête par seconde au plus
+pub const DEBIT_NOMINATIM: f64 = 1.0;
+
+/// Débit raisonnable vers l'instance publique de Photon, sans limite documentée
+pub const DEBIT_PHOTON: f64 = 10.0;
+
+/// Éléments d'adresse communs aux deux services
+struct Elements {
+    numero: Option<String>,
+    voie: Option<String>,
+    cp: Option<String>,
+    ville: Option<String>,
+}
+
+impl Elements {
+    /// Granularité au sens de la BAN
+    fn type_resultat(&self) -> &'static str {
+        match (&self.numero, &self.voie, &self.ville) {
+            (Some(_), Some(_), _) => "housenumber",
+            (None, Some(_), _) => "street",
+            (_, None, Some(_)) => "municipality",
+            _ => "locality",
+        }
+    }
+
+    fn label(&self) -> String {
+        let voie = [&self.numero, &self.voie]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let commune = [&self.cp, &self.ville]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        [voie, commune]
+            .into_iter()
+            .filter(|partie| !partie.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn correspondance(
+        self,
+        requete: &Requete,
+        id: String,
+        longitude: f64,
+        latitude: f64,
+    ) -> Correspondance {
+        let label = self.label();
+        let demande = normaliser(&format!(
+            "{} {} {}",
+            requete.adresse, requete.cp, requete.ville
+        ));
+        Correspondance {
+            score: strsim::jaro_winkler(&demande, &normaliser(&label)),
+            type_resultat: self.type_resultat().to_string(),
+            label,
+            id,
+            citycode: String::new(),
+            postcode: self.cp,
+            city: self.ville.unwrap_or_default(),
+            housenumber: self.numero,
+            street: self.voie,
+            longitude,
+            latitude,
+        }
+    }
+}
+
+/// Candidats triés par score décroissant
+fn classer(mut correspondances: Vec<Correspondance>) -> Vec<Correspondance> {
+    correspondances.sort_by(|a, b| b.score.total_cmp(&a.score));
+    correspondances
+}
+
+/// Résultat de /search?format=jsonv2&addressdetails=1
+#[derive(Deserialize)]
+struct ResultatNominatim {
+    osm_type: String,
+    osm_id: u64,
+    lat: String,
+    lon: String,
+    address: AdresseNominatim,
+}
+
+#[derive(Deserialize)]
+struct AdresseNominatim {
+    house_number: Option<String>,
+    road: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+    town: Option<String>,
+    village: Option<String>,
+    municipality: Option<String>,
+}
+
+/// Client d'une instance Nominatim
+pub struct ClientNominatim {
+    http: ClientHttp,
+    base: Url,
+}
+
+impl ClientNominatim {
+    pub fn new(base: &str, http: ClientHttp) -> Result<Self, ErreurGeocodage> {
+        Ok(ClientNominatim {
+            http,
+            base: url_de_base(base)?,
+        })
+    }
+}
+
+impl Geocodeur for ClientNominatim {
+    fn geocoder(
+        &self,
+        requete: &Requete,
+        limite: usize,
+    ) -> Result<Vec<Correspondance>, ErreurGeocodage> {
+        let mut url = self.base.join("search").unwrap();
+        url.query_pairs_mut()
+            .append_pair("q", &requete.texte())
+            .append_pair("format", "jsonv2")
+            .append_pair("addressdetails", "1")
+            .append_pair("countrycodes", "fr")
+            .append_pair("limit", &limite.to_string());
+
+        let resultats = self
+            .http
+            .envoyer(|| self.http.get(url.clone()))?
+            .json::<Vec<ResultatNominatim>>()?;
+
+        let correspondances = resultats
+            .into_iter()
+            .map(|r| {
+                let coordonnees = (r.lon.parse::<f64>(), r.lat.parse::<f64>());
+                let (Ok(longitude), Ok(latitude)) = coordonnees else {
+                    return Err(ErreurGeocodage::Reponse(format!(
+                        "coordonnées illisibles : {}, {}",
+                        r.lon, r.lat
+                    )));
+                };
+                let adresse = r.address;
+                let elements = Elements {
+                    numero: adresse.house_number,
+                    voie: adresse.road,
+                    cp: adresse.postcode,
+                    ville: adresse
+                        .city
+                        .or(adresse.town)
+                        .or(adresse.village)
+                        .or(adresse.municipality),
+                };
+                let id = format!("{}/{}", r.osm_type, r.osm_id);
+                Ok(elements.correspondance(requete, id, longitude, latitude))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(classer(correspondances))
+    }
+}
+
+/// Réponse GeoJSON de /api
+#[derive(Deserialize)]
+struct ReponsePhoton {
+    features: Vec<FeaturePhoton>,
+}
+
+#[derive(Deserialize)]
+struct FeaturePhoton {
+    geometry: GeometriePhoton,
+    properties: ProprietesPhoton,
+}
+
+#[derive(Deserialize)]
+struct GeometriePhoton {
+    coordinates: [f64; 2],
+}
+
+#[derive(Deserialize)]
+struct ProprietesPhoton {
+    osm_type: String,
+    osm_id: u64,
+    #[serde(rename = "type")]
+    type_resultat: Option<String>,
+    name: Option<String>,
+    housenumber: Option<String>,
+    street: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+}
+
+/// Client d'une instance Photon
+pub struct ClientPhoton {
+    http: ClientHttp,
+    base: Url,
+}
+
+impl ClientPhoton {
+    pub fn new(base: &str, http: ClientHttp) -> Result<Self, ErreurGeocodage> {
+        Ok(ClientPhoton {
+            http,
+            base: url_de_base(base)?,
+        })
+    }
+}
+
+impl Geocodeur for ClientPhoton {
+    fn geocoder(
+        &self,
+        requete: &Requete,
+        limite: usize,
+    ) -> Result<Vec<Correspondance>, ErreurGeocodage> {
+        let mut url = self.base.join("api").unwrap();
+        url.query_pairs_mut()
+            .append_pair("q", &requete.texte())
+            .append_pair("lang", "fr")
+            .append_pair("limit", &limite.to_string());
+
+        let reponse = self
+            .http
+            .envoyer(|| self.http.get(url.clone()))?
+            .json::<ReponsePhoton>()?;
+
+        let correspondances = reponse
+            .features
+            .into_iter()
+            .map(|feature| {
+                let p = feature.properties;
+                // Pour une voie ou une commune, Photon met son nom dans `name`
+                let (voie, ville) = match p.type_resultat.as_deref() {
+                    Some("street") => (p.name.or(p.street), p.city),
+                    Some("city") => (None, p.city.or(p.name)),
+                    _ => (p.street, p.city),
+                };
+                let elements = Elements {
+                    numero: p.housenumber,
+                    voie,
+                    cp: p.postcode,
+                    ville,
+                };
+                let [longitude, latitude] = feature.geometry.coordinates;
+                let id = format!("{}/{}", p.osm_type, p.osm_id);
+                elements.correspondance(requete, id, longitude, latitude)
+            })
+            .collect();
+        Ok(classer(correspondances))
+    }
+}
@@ -0,0 +1,61 @@
+//! Point de reprise d'une vérification interrompue
+//!
+//! Le fichier `<sortie>.checkpoint` indique combien de lignes du fichier
+//! d'entrée ont été écrites, la taille du fichier de sortie à ce moment-là
+//! et la forme de ses lignes (colonnes, séparateur, encodage).
+//! À la reprise, la sortie est tronquée à cette taille (une ligne à moitié
+//! écrite lors d'un arrêt brutal disparaît) puis complétée.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+pub struct PointDeReprise {
+    /// SHA-256 du fichier d'entrée, pour ne pas reprendre sur un autre fichier
+    pub empreinte_entree: String,
+    /// SHA-256 de l'en-tête et des options d'écriture, pour ne pas mélanger deux
+    /// formes de lignes
+    pub forme_sortie: String,
+    /// Lignes d'entrée (hors en-tête) déjà écrites dans la sortie
+    pub lignes_traitees: u64,
+    /// Taille de la sortie correspondant à ces lignes
+    pub octets_ecrits: u64,
+}
+
+/// Empreinte SHA-256 d'un fichier, en hexadécimal
+pub fn empreinte(chemin: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(chemin)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Empreinte SHA-256 d'un texte, en hexadécimal
+pub fn empreinte_texte(texte: &str) -> String {
+    format!("{:x}", Sha256::digest(texte.as_bytes()))
+}
+
+/// Emplacement du point de reprise associé à un fichier de sortie
+pub fn chemin(sortie: &str) -> PathBuf {
+    PathBuf::from(format!("{sortie}.checkpoint"))
+}
+
+/// Point de reprise existant, s'il y en a un
+pub fn lire(chemin: &Path) -> Result<Option<PointDeReprise>, Box<dyn Error>> {
+    match fs::read_to_string(chemin) {
+        Ok(contenu) => Ok(Some(serde_json::from_str(&contenu)?)),
+        Err(erreur) if erreur.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(erreur) => Err(erreur.into()),
+    }
+}
+
+/// Enregistre le point de reprise via un fichier temporaire, pour ne jamais
+/// laisser un point de reprise à moitié écrit
+pub fn enregistrer(chemin: &Path, point: &PointDeReprise) -> io::Result<()> {
+    let temporaire = chemin.with_extension("checkpoint.tmp");
+    fs::write(&temporaire, serde_json::to_string(point)?)?;
+    fs::rename(temporaire, chemin)
+}
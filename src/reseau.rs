@@ -0,0 +1,162 @@
+//! Appels HTTP partagés par les géocodeurs en ligne : limitation de débit et nouvelles tentatives
+
+use crate::geocodeur::ErreurGeocodage;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Premier délai avant une nouvelle tentative, doublé à chaque échec
+const DELAI_INITIAL: Duration = Duration::from_millis(500);
+
+/// Délai maximal entre deux tentatives
+const DELAI_MAX: Duration = Duration::from_secs(30);
+
+/// Seau à jetons partagé entre les threads de vérification
+///
+/// Un 429 suspend tous les threads jusqu'à la date indiquée par le serveur.
+pub struct Limiteur {
+    /// Requêtes par seconde, sans limite si absent
+    debit: Option<f64>,
+    etat: Mutex<EtatLimiteur>,
+}
+
+struct EtatLimiteur {
+    jetons: f64,
+    mise_a_jour: Instant,
+    reprise: Option<Instant>,
+}
+
+impl Limiteur {
+    pub fn new(debit: Option<f64>) -> Self {
+        Limiteur {
+            debit,
+            etat: Mutex::new(EtatLimiteur {
+                jetons: 1.0,
+                mise_a_jour: Instant::now(),
+                reprise: None,
+            }),
+        }
+    }
+
+    /// Bloque jusqu'à ce qu'une requête puisse partir
+    pub fn attendre(&self) {
+        loop {
+            let attente = {
+                let mut etat = self.etat.lock().unwrap();
+                let maintenant = Instant::now();
+                match etat.reprise {
+                    Some(reprise) if reprise > maintenant => reprise - maintenant,
+                    _ => {
+                        let Some(debit) = self.debit else { return };
+                        // Une seconde de requêtes peut partir d'un coup après une pause
+                        let ecoule = (maintenant - etat.mise_a_jour).as_secs_f64();
+                        etat.jetons = (etat.jetons + ecoule * debit).min(debit.max(1.0));
+                        etat.mise_a_jour = maintenant;
+                        if etat.jetons >= 1.0 {
+                            etat.jetons -= 1.0;
+                            return;
+                        }
+                        Duration::from_secs_f64((1.0 - etat.jetons) / debit)
+                    }
+                }
+            };
+            thread::sleep(attente);
+        }
+    }
+
+    /// Aucune requête ne part avant `duree`
+    pub fn suspendre(&self, duree: Duration) {
+        let mut etat = self.etat.lock().unwrap();
+        let reprise = Instant::now() + duree;
+        if etat.reprise.is_none_or(|actuelle| actuelle < reprise) {
+            etat.reprise = Some(reprise);
+        }
+    }
+}
+
+/// Client HTTP avec limitation de débit et nouvelles tentatives sur les erreurs passagères
+pub struct ClientHttp {
+    http: Client,
+    limiteur: Limiteur,
+    tentatives_max: u32,
+}
+
+impl ClientHttp {
+    pub fn new(debit: Option<f64>, tentatives_max: u32) -> Result<Self, ErreurGeocodage> {
+        // Nominatim et Photon demandent un User-Agent identifiant l'application
+        let http = Client::builder()
+            .user_agent(concat!("check_address/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(ClientHttp {
+            http,
+            limiteur: Limiteur::new(debit),
+            tentatives_max,
+        })
+    }
+
+    pub fn get(&self, url: reqwest::Url) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    pub fn post(&self, url: reqwest::Url) -> RequestBuilder {
+        self.http.post(url)
+    }
+
+    /// Envoie la requête produite par `requete`, reconstruite à chaque tentative
+    ///
+    /// Les 429, 5xx, délais dépassés et erreurs de connexion sont retentés
+    /// jusqu'à `tentatives_max` fois ; les autres erreurs HTTP sont renvoyées
+    /// immédiatement.
+    pub fn envoyer(
+        &self,
+        requete: impl Fn() -> RequestBuilder,
+    ) -> Result<Response, ErreurGeocodage> {
+        let mut tentative = 0;
+        loop {
+            self.limiteur.attendre();
+            let (erreur, retry_after) = match requete().send() {
+                Ok(reponse) if !reessayable(reponse.status()) => {
+                    return Ok(reponse.error_for_status()?);
+                }
+                Ok(reponse) => {
+                    let retry_after = retry_after(&reponse);
+                    (reponse.error_for_status().unwrap_err(), retry_after)
+                }
+                Err(erreur) if erreur.is_timeout() || erreur.is_connect() => (erreur, None),
+                Err(erreur) => return Err(erreur.into()),
+            };
+
+            if tentative >= self.tentatives_max {
+                return Err(erreur.into());
+            }
+            tentative += 1;
+            match retry_after {
+                // Le serveur demande à tout le monde d'attendre
+                Some(duree) => self.limiteur.suspendre(duree),
+                None => thread::sleep(delai_avec_gigue(tentative)),
+            }
+        }
+    }
+}
+
+fn reessayable(statut: StatusCode) -> bool {
+    statut == StatusCode::TOO_MANY_REQUESTS || statut.is_server_error()
+}
+
+/// En-tête Retry-After exprimé en secondes (la forme date n'est pas gérée)
+fn retry_after(reponse: &Response) -> Option<Duration> {
+    let valeur = reponse.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    valeur.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Délai exponentiel, tiré au hasard dans sa moitié haute pour que les threads
+/// ne réessaient pas tous en même temps
+fn delai_avec_gigue(tentative: u32) -> Duration {
+    let delai = DELAI_INITIAL
+        .saturating_mul(1 << (tentative - 1).min(16))
+        .min(DELAI_MAX);
+    delai.mul_f64(0.5 + fastrand::f64() / 2.0)
+}
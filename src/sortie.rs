@@ -0,0 +1,226 @@
+//! Écriture du fichier _chk, ligne par ligne et dans l'ordre du fichier d'entrée
+
+use crate::dialecte::Dialecte;
+use crate::encodage::{Politique, Transcodeur};
+use csv::StringRecord;
+use encoding_rs::{Encoding, UTF_8};
+use serde_json::{Map, Value, json};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Colonnes de validation écrites comme des nombres dans les formats typés
+pub const COLONNES_NUMERIQUES: [&str; 3] = ["ban_score", "longitude", "latitude"];
+
+/// Colonnes de validation écrites comme des booléens en JSON (0 ou 1 en SQLite)
+pub const COLONNES_BOOLEENNES: [&str; 1] = ["adresse_valide"];
+
+/// Destination des lignes vérifiées, en-tête compris
+pub trait Sortie {
+    /// Écrit une ligne : colonnes d'entrée et colonnes de validation
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>>;
+
+    /// Rend durable ce qui a été écrit et renvoie la taille du fichier à
+    /// enregistrer dans le point de reprise, si la sortie peut être reprise
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>>;
+
+    /// Termine le fichier et renvoie un avertissement éventuel pour l'utilisateur
+    fn terminer(self: Box<Self>) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// Sortie texte délimitée, dans l'encodage demandé
+pub struct SortieCsv {
+    wtr: csv::Writer<Transcodeur<File>>,
+    encodage: &'static Encoding,
+    politique: Politique,
+    /// Vidé après chaque ligne pour savoir lesquelles contiennent des caractères non représentables
+    ligne_par_ligne: bool,
+    lignes_non_representables: u64,
+}
+
+impl SortieCsv {
+    /// Écrit BOM et en-tête seulement si le fichier est vide, c'est-à-dire hors reprise
+    pub fn new(
+        mut fichier: File,
+        entete: &StringRecord,
+        forme: Dialecte,
+        encodage: &'static Encoding,
+        politique: Politique,
+        bom: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let vide = fichier.metadata()?.len() == 0;
+        if vide && bom && encodage == UTF_8 {
+            fichier.write_all("\u{FEFF}".as_bytes())?;
+        }
+        let mut wtr = forme
+            .ecrivain()
+            .has_headers(false)
+            .from_writer(Transcodeur::new(fichier, encodage, politique));
+        if vide {
+            wtr.write_record(entete)?;
+        }
+        Ok(SortieCsv {
+            wtr,
+            encodage,
+            politique,
+            ligne_par_ligne: encodage != UTF_8,
+            lignes_non_representables: 0,
+        })
+    }
+}
+
+impl Sortie for SortieCsv {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        let avant = self.wtr.get_ref().non_representables();
+        self.wtr.write_record(ligne)?;
+        if self.ligne_par_ligne {
+            self.wtr.flush()?;
+        }
+        if self.wtr.get_ref().non_representables() > avant {
+            self.lignes_non_representables += 1;
+        }
+        Ok(())
+    }
+
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        self.wtr.flush()?;
+        Ok(Some(self.wtr.get_ref().get_ref().metadata()?.len()))
+    }
+
+    fn terminer(mut self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.wtr.flush()?;
+        if self.lignes_non_representables == 0 {
+            return Ok(None);
+        }
+        let traitement = match self.politique {
+            Politique::Transliterate => "remplacés par un équivalent approché",
+            _ => "remplacés par « ? »",
+        };
+        Ok(Some(format!(
+            "{} lignes contiennent des caractères absents de {} ({traitement})",
+            self.lignes_non_representables,
+            self.encodage.name()
+        )))
+    }
+}
+
+/// Ligne sous forme d'objet JSON : les colonnes d'entrée restent du texte,
+/// les colonnes numériques et booléennes de validation sont typées (null si vides)
+fn objet(entete: &StringRecord, ligne: &StringRecord) -> Map<String, Value> {
+    entete
+        .iter()
+        .zip(ligne.iter())
+        .map(|(colonne, valeur)| {
+            let typee = if COLONNES_NUMERIQUES.contains(&colonne) {
+                Some(
+                    valeur
+                        .parse::<f64>()
+                        .map_or(Value::Null, |nombre| json!(nombre)),
+                )
+            } else if COLONNES_BOOLEENNES.contains(&colonne) {
+                Some(valeur.parse::<bool>().map_or(Value::Null, Value::Bool))
+            } else {
+                None
+            };
+            let valeur = typee.unwrap_or_else(|| Value::String(valeur.to_string()));
+            (colonne.to_string(), valeur)
+        })
+        .collect()
+}
+
+/// Un objet JSON par ligne (JSON Lines)
+pub struct SortieJsonl {
+    fichier: BufWriter<File>,
+    entete: StringRecord,
+}
+
+impl SortieJsonl {
+    pub fn new(fichier: File, entete: &StringRecord) -> Self {
+        SortieJsonl {
+            fichier: BufWriter::new(fichier),
+            entete: entete.clone(),
+        }
+    }
+}
+
+impl Sortie for SortieJsonl {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.fichier, &objet(&self.entete, ligne))?;
+        self.fichier.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        self.fichier.flush()?;
+        Ok(Some(self.fichier.get_ref().metadata()?.len()))
+    }
+
+    fn terminer(mut self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.fichier.flush()?;
+        Ok(None)
+    }
+}
+
+/// Début d'une FeatureCollection, écrit avant la première ligne
+const DEBUT_GEOJSON: &str = "{\"type\":\"FeatureCollection\",\"features\":[";
+
+/// FeatureCollection GeoJSON : une Feature Point par ligne, sans géométrie
+/// quand l'adresse n'a pas été trouvée
+///
+/// Le fichier n'est refermé qu'à la fin : un point de reprise le laisse
+/// ouvert, prêt à être complété.
+pub struct SortieGeoJson {
+    fichier: BufWriter<File>,
+    entete: StringRecord,
+    colonnes_coordonnees: Option<(usize, usize)>,
+    premiere: bool,
+}
+
+impl SortieGeoJson {
+    pub fn new(mut fichier: File, entete: &StringRecord) -> Result<Self, Box<dyn Error>> {
+        let taille = fichier.metadata()?.len();
+        if taille == 0 {
+            fichier.write_all(DEBUT_GEOJSON.as_bytes())?;
+        }
+        let colonne = |nom: &str| entete.iter().position(|titre| titre == nom);
+        Ok(SortieGeoJson {
+            fichier: BufWriter::new(fichier),
+            entete: entete.clone(),
+            colonnes_coordonnees: colonne("longitude").zip(colonne("latitude")),
+            premiere: taille <= DEBUT_GEOJSON.len() as u64,
+        })
+    }
+}
+
+impl Sortie for SortieGeoJson {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        let coordonnees = self.colonnes_coordonnees.and_then(|(longitude, latitude)| {
+            let longitude = ligne.get(longitude)?.parse::<f64>().ok()?;
+            let latitude = ligne.get(latitude)?.parse::<f64>().ok()?;
+            Some([longitude, latitude])
+        });
+        let feature = json!({
+            "type": "Feature",
+            "geometry": coordonnees.map(|c| json!({ "type": "Point", "coordinates": c })),
+            "properties": objet(&self.entete, ligne),
+        });
+        if !self.premiere {
+            self.fichier.write_all(b",")?;
+        }
+        self.premiere = false;
+        self.fichier.write_all(b"\n")?;
+        serde_json::to_writer(&mut self.fichier, &feature)?;
+        Ok(())
+    }
+
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        self.fichier.flush()?;
+        Ok(Some(self.fichier.get_ref().metadata()?.len()))
+    }
+
+    fn terminer(mut self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.fichier.write_all(b"\n]}\n")?;
+        self.fichier.flush()?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,156 @@
+//! Classeurs Excel et OpenDocument : lecture des feuilles et écriture du _chk en .xlsx
+
+use crate::sortie::{COLONNES_NUMERIQUES, Sortie};
+use calamine::{Data, Reader, open_workbook_auto};
+use csv::StringRecord;
+use rust_xlsxwriter::{
+    Color, ConditionalFormatText, ConditionalFormatTextRule, Format, Workbook, Worksheet,
+};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Extensions lues comme des classeurs plutôt que comme du texte
+const EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// Vrai si le fichier est un classeur, d'après son extension
+pub fn est_classeur(chemin: &Path) -> bool {
+    chemin
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Feuille lue et ses lignes, cellules converties en texte
+pub struct Feuille {
+    pub nom: String,
+    pub lignes: Vec<StringRecord>,
+}
+
+/// Lit une feuille désignée par son nom ou son numéro (à partir de 1), la première par défaut
+///
+/// Les cellules texte sont lues telles quelles : un code postal saisi comme
+/// texte garde son zéro initial.
+pub fn lire(chemin: &Path, feuille: Option<&str>) -> Result<Feuille, Box<dyn Error>> {
+    let mut classeur = open_workbook_auto(chemin)?;
+    let noms = classeur.sheet_names();
+    let nom = match feuille {
+        None => noms.first(),
+        Some(feuille) => noms.iter().find(|nom| *nom == feuille).or_else(|| {
+            feuille
+                .parse::<usize>()
+                .ok()
+                .and_then(|numero| noms.get(numero.checked_sub(1)?))
+        }),
+    }
+    .ok_or_else(|| {
+        format!(
+            "feuille « {} » introuvable (feuilles : {})",
+            feuille.unwrap_or_default(),
+            noms.join(", ")
+        )
+    })?
+    .clone();
+
+    let plage = classeur.worksheet_range(&nom)?;
+    let lignes = plage
+        .rows()
+        // Les lignes entièrement vides ne sont pas des adresses
+        .filter(|cellules| cellules.iter().any(|cellule| *cellule != Data::Empty))
+        .map(|cellules| cellules.iter().map(ToString::to_string).collect())
+        .collect();
+    Ok(Feuille { nom, lignes })
+}
+
+/// Fichier _chk au format .xlsx, écrit en une fois à la fin de la vérification
+pub struct ClasseurSortie {
+    chemin: PathBuf,
+    feuille: Worksheet,
+    ligne: u32,
+    numeriques: Vec<bool>,
+    /// Colonne adresse_valide, mise en couleur
+    colonne_validite: Option<u16>,
+}
+
+impl ClasseurSortie {
+    pub fn new(
+        chemin: &Path,
+        nom_feuille: &str,
+        entete: &StringRecord,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut feuille = Worksheet::new();
+        feuille.set_name(nom_feuille)?;
+        let gras = Format::new().set_bold();
+        for (colonne, titre) in entete.iter().enumerate() {
+            feuille.write_string_with_format(0, colonne as u16, titre, &gras)?;
+        }
+        feuille.set_freeze_panes(1, 0)?;
+        Ok(ClasseurSortie {
+            chemin: chemin.to_path_buf(),
+            feuille,
+            ligne: 1,
+            numeriques: entete
+                .iter()
+                .map(|titre| COLONNES_NUMERIQUES.contains(&titre))
+                .collect(),
+            colonne_validite: entete
+                .iter()
+                .position(|titre| titre == "adresse_valide")
+                .map(|colonne| colonne as u16),
+        })
+    }
+
+    /// Ajoute la mise en forme conditionnelle et enregistre le classeur
+    fn enregistrer(mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(colonne) = self.colonne_validite.filter(|_| self.ligne > 1) {
+            // Couleurs « bon » et « mauvais » d'Excel
+            let couleurs = [("true", 0xC6EFCE, 0x006100), ("false", 0xFFC7CE, 0x9C0006)];
+            for (valeur, fond, texte) in couleurs {
+                let format = Format::new()
+                    .set_background_color(Color::RGB(fond))
+                    .set_font_color(Color::RGB(texte));
+                let regle = ConditionalFormatText::new()
+                    .set_rule(ConditionalFormatTextRule::BeginsWith(valeur.to_string()))
+                    .set_format(format);
+                self.feuille
+                    .add_conditional_format(1, colonne, self.ligne - 1, colonne, &regle)?;
+            }
+        }
+        let mut classeur = Workbook::new();
+        classeur.push_worksheet(self.feuille);
+        classeur.save(&self.chemin)?;
+        Ok(())
+    }
+}
+
+impl Sortie for ClasseurSortie {
+    fn ecrire(&mut self, ligne: &StringRecord) -> Result<(), Box<dyn Error>> {
+        for (colonne, valeur) in ligne.iter().enumerate() {
+            let nombre = self
+                .numeriques
+                .get(colonne)
+                .is_some_and(|numerique| *numerique)
+                .then(|| valeur.parse::<f64>().ok())
+                .flatten();
+            match nombre {
+                Some(nombre) => self
+                    .feuille
+                    .write_number(self.ligne, colonne as u16, nombre)?,
+                None => self
+                    .feuille
+                    .write_string(self.ligne, colonne as u16, valeur)?,
+            };
+        }
+        self.ligne += 1;
+        Ok(())
+    }
+
+    /// Le classeur n'est écrit qu'à la fin : pas de reprise possible
+    fn point_de_reprise(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    fn terminer(self: Box<Self>) -> Result<Option<String>, Box<dyn Error>> {
+        self.enregistrer()?;
+        Ok(None)
+    }
+}
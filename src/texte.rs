@@ -0,0 +1,83 @@
+//! Outils de comparaison de libellés (accents, casse, ponctuation)
+
+/// Équivalent sans accent d'une lettre minuscule, si elle en porte un
+fn replier_minuscule(c: char) -> Option<&'static str> {
+    let replie = match c {
+        'à' | 'â' | 'ä' | 'á' | 'ã' | 'å' | 'ą' | 'ă' => "a",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' | 'đ' => "d",
+        'é' | 'è' | 'ê' | 'ë' | 'ę' | 'ě' => "e",
+        'î' | 'ï' | 'í' | 'ì' => "i",
+        'ł' | 'ľ' => "l",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ô' | 'ö' | 'ó' | 'ò' | 'õ' | 'ø' | 'ő' => "o",
+        'ř' => "r",
+        'ś' | 'š' | 'ș' | 'ş' => "s",
+        'ť' | 'ț' | 'ţ' => "t",
+        'ù' | 'û' | 'ü' | 'ú' | 'ů' | 'ű' => "u",
+        'ÿ' | 'ý' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'œ' => "oe",
+        'æ' => "ae",
+        'ß' => "ss",
+        _ => return None,
+    };
+    Some(replie)
+}
+
+/// Remplace les lettres accentuées et ligatures par leur équivalent ASCII, en conservant la casse
+pub fn sans_accents(texte: &str) -> String {
+    let mut resultat = String::with_capacity(texte.len());
+    for c in texte.chars() {
+        let minuscule = c.to_lowercase().next().unwrap_or(c);
+        match replier_minuscule(minuscule) {
+            Some(replie) if c.is_uppercase() => resultat.push_str(&replie.to_uppercase()),
+            Some(replie) => resultat.push_str(replie),
+            None => resultat.push(c),
+        }
+    }
+    resultat
+}
+
+/// Forme de comparaison d'un libellé : majuscules sans accents, ponctuation
+/// remplacée par des espaces, espaces multiples réduits
+pub fn normaliser(texte: &str) -> String {
+    sans_accents(texte)
+        .to_uppercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|mot| !mot.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Mentions d'arrondissement après le nom de Paris, Lyon ou Marseille
+fn est_arrondissement(mot: &str) -> bool {
+    let chiffres = mot.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    matches!(mot, "ARRONDISSEMENT" | "ARR")
+        || (!chiffres.is_empty()
+            && chiffres.bytes().all(|b| b.is_ascii_digit())
+            && matches!(&mot[chiffres.len()..], "" | "E" | "EME" | "ER" | "ERE"))
+}
+
+/// Forme de comparaison d'un nom de commune : « St »/« Ste » développés,
+/// mention CEDEX et arrondissements de Paris, Lyon et Marseille retirés
+pub fn nom_commune(texte: &str) -> String {
+    let normalise = normaliser(texte);
+    let mut mots: Vec<&str> = normalise
+        .split(' ')
+        .map(|mot| match mot {
+            "ST" => "SAINT",
+            "STE" => "SAINTE",
+            _ => mot,
+        })
+        .collect();
+    if let Some(cedex) = mots.iter().position(|mot| *mot == "CEDEX") {
+        mots.truncate(cedex);
+    }
+    if matches!(mots.first(), Some(&("PARIS" | "LYON" | "MARSEILLE"))) {
+        while mots.len() > 1 && mots.last().is_some_and(|mot| est_arrondissement(mot)) {
+            mots.pop();
+        }
+    }
+    mots.join(" ")
+}
@@ -0,0 +1,272 @@
+//! Repérage de la ligne de voie parmi les lignes d'adresse
+//!
+//! Les fichiers clients répartissent souvent l'adresse sur plusieurs colonnes
+//! (bâtiment, résidence, voie, lieu-dit, boîte postale) dans un ordre variable :
+//! seule la ligne de voie est envoyée au géocodeur, les autres sont conservées
+//! comme complément. La ligne de voie est ensuite découpée en numéro, indice
+//! de répétition, type et nom de voie.
+
+use crate::normalisation::{ABREVIATIONS_VOIE, normaliser_voie, normaliser_voie_complete};
+use crate::texte::normaliser;
+
+/// Types de voie écrits en entier, sous forme normalisée ; leurs abréviations
+/// sont dans `normalisation::ABREVIATIONS_VOIE`
+const TYPES_VOIE: &[&str] = &[
+    "RUE",
+    "AVENUE",
+    "BOULEVARD",
+    "CHEMIN",
+    "ALLEE",
+    "PLACE",
+    "IMPASSE",
+    "ROUTE",
+    "QUAI",
+    "COURS",
+    "ESPLANADE",
+    "PASSAGE",
+    "SQUARE",
+    "SENTIER",
+    "SENTE",
+    "VOIE",
+    "PROMENADE",
+    "ROND",
+    "CARREFOUR",
+    "ROCADE",
+    "FAUBOURG",
+    "CHAUSSEE",
+    "MAIL",
+    "GALERIE",
+    "PARVIS",
+    "RUELLE",
+    "VENELLE",
+    "TRAVERSE",
+    "LOTISSEMENT",
+    "HAMEAU",
+    "CITE",
+    "CLOS",
+    "VILLA",
+    "DOMAINE",
+    "QUARTIER",
+    "PARC",
+];
+
+/// Mots annonçant, en début de ligne, un complément de distribution plutôt qu'une voie
+const COMPLEMENTS: &[&str] = &[
+    "BP",
+    "CS",
+    "TSA",
+    "CEDEX",
+    "BAT",
+    "BATIMENT",
+    "BT",
+    "RESIDENCE",
+    "RES",
+    "IMMEUBLE",
+    "IMM",
+    "APPARTEMENT",
+    "APPT",
+    "APT",
+    "ESCALIER",
+    "ESC",
+    "ETAGE",
+    "ETG",
+    "ENTREE",
+    "CHEZ",
+    "ZI",
+    "ZA",
+    "ZAC",
+];
+
+/// Mots annonçant un lieu-dit : à défaut de voie, c'est la ligne à géocoder
+const LIEUX_DITS: &[&str] = &["LIEU", "LIEUDIT", "LD"];
+
+/// Vrai si le mot normalisé est un type de voie, abrégé ou non
+pub fn est_type_voie(mot: &str) -> bool {
+    TYPES_VOIE.contains(&mot) || ABREVIATIONS_VOIE.iter().any(|(abrege, _)| *abrege == mot)
+}
+
+/// Vraisemblance qu'une ligne soit la ligne de voie
+fn score(ligne: &str) -> i32 {
+    let normalisee = normaliser(ligne);
+    let mots: Vec<&str> = normalisee
+        .split(' ')
+        .filter(|mot| !mot.is_empty())
+        .collect();
+    let Some(premier) = mots.first() else {
+        return i32::MIN;
+    };
+    let mut score = 0;
+    if premier.starts_with(|c: char| c.is_ascii_digit()) {
+        score += 1;
+    }
+    if mots.iter().take(4).any(|mot| est_type_voie(mot)) {
+        score += 2;
+    }
+    if LIEUX_DITS.contains(premier) {
+        score += 1;
+    }
+    if mots.iter().take(2).any(|mot| COMPLEMENTS.contains(mot)) {
+        score -= 3;
+    }
+    score
+}
+
+/// Ligne de voie et complément (les autres lignes non vides, dans l'ordre)
+///
+/// La ligne retenue est la plus vraisemblable ; en cas d'égalité, la première.
+/// Un lieu-dit seul reste la ligne envoyée au géocodeur.
+pub fn separer<'a>(lignes: &[&'a str]) -> (&'a str, Vec<&'a str>) {
+    let lignes: Vec<&str> = lignes
+        .iter()
+        .map(|ligne| ligne.trim())
+        .filter(|ligne| !ligne.is_empty())
+        .collect();
+    let Some(voie) = lignes
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, ligne)| score(ligne))
+        .map(|(i, _)| i)
+    else {
+        return ("", Vec::new());
+    };
+    let complement = lignes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != voie)
+        .map(|(_, ligne)| *ligne)
+        .collect();
+    (lignes[voie], complement)
+}
+
+/// Indices de répétition reconnus après le numéro
+const INDICES_REPETITION: &[&str] = &["BIS", "TER", "QUATER", "QUINQUIES"];
+
+/// Éléments d'une ligne de voie, vides s'ils sont absents
+#[derive(Debug, Default)]
+pub struct Decoupage {
+    pub numero: String,
+    /// bis, ter, quater…
+    pub indice: String,
+    pub type_voie: String,
+    pub nom: String,
+}
+
+/// Découpe une ligne de voie saisie, abréviations développées :
+/// « 12B R DES LILAS » donne 12, bis, RUE et DES LILAS
+pub fn decomposer(ligne: &str) -> Decoupage {
+    decouper(&normaliser_voie(ligne))
+}
+
+/// Découpe la voie renvoyée par le géocodeur, déjà écrite en entier : son nom
+/// n'est pas retouché (« Rue du Col » ne devient pas « Rue du Colonel »)
+pub fn decomposer_resultat(ligne: &str) -> Decoupage {
+    decouper(&normaliser_voie_complete(ligne))
+}
+
+/// Découpe une ligne normalisée, numéro et indice séparés
+fn decouper(normalisee: &str) -> Decoupage {
+    let mut mots = normalisee
+        .split(' ')
+        .filter(|mot| !mot.is_empty())
+        .peekable();
+    let numero = mots.next_if(|mot| mot.starts_with(|c: char| c.is_ascii_digit()));
+    let indice = numero
+        .and_then(|_| mots.next_if(|mot| INDICES_REPETITION.contains(&normaliser(mot).as_str())));
+    // « Rond-point » est normalisé en deux mots : seul le premier est cherché
+    let type_voie =
+        mots.next_if(|mot| normaliser(mot).split(' ').next().is_some_and(est_type_voie));
+    Decoupage {
+        numero: numero.unwrap_or_default().to_string(),
+        indice: indice.unwrap_or_default().to_lowercase(),
+        type_voie: type_voie.unwrap_or_default().to_string(),
+        nom: mots.collect::<Vec<_>>().join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lieu_dit_prefere_aux_complements() {
+        assert_eq!(
+            separer(&["Bât A", "Lieu-dit Le Moulin", ""]),
+            ("Lieu-dit Le Moulin", vec!["Bât A"])
+        );
+        assert_eq!(
+            separer(&["BP 12", "LD Les Granges", "Appt 4"]),
+            ("LD Les Granges", vec!["BP 12", "Appt 4"])
+        );
+        assert_eq!(
+            separer(&["Résidence les Pins", "Lieudit Kerbras", "CS 30012"]),
+            ("Lieudit Kerbras", vec!["Résidence les Pins", "CS 30012"])
+        );
+    }
+
+    #[test]
+    fn voie_preferee_au_lieu_dit() {
+        assert_eq!(
+            separer(&["Lieu-dit Le Moulin", "12 rue de la Gare"]),
+            ("12 rue de la Gare", vec!["Lieu-dit Le Moulin"])
+        );
+        assert_eq!(
+            separer(&["Bât A", "3 bis avenue Foch", "CEDEX 9"]),
+            ("3 bis avenue Foch", vec!["Bât A", "CEDEX 9"])
+        );
+    }
+
+    fn decoupage(ligne: &str) -> (String, String, String, String) {
+        let d = decomposer(ligne);
+        (d.numero, d.indice, d.type_voie, d.nom)
+    }
+
+    #[test]
+    fn voie_saisie_decoupee() {
+        assert_eq!(
+            decoupage("12B R DES LILAS"),
+            ("12".into(), "bis".into(), "RUE".into(), "DES LILAS".into())
+        );
+        assert_eq!(
+            decoupage("3 t, Imp. du Dr Roux"),
+            (
+                "3".into(),
+                "ter".into(),
+                "Impasse".into(),
+                "du Docteur Roux".into()
+            )
+        );
+        assert_eq!(
+            decoupage("12 Villa des Roses"),
+            ("12".into(), "".into(), "Villa".into(), "des Roses".into())
+        );
+        assert_eq!(
+            decoupage("Cité Jardin"),
+            ("".into(), "".into(), "Cité".into(), "Jardin".into())
+        );
+        assert_eq!(
+            decoupage("Lieu-dit Le Moulin"),
+            ("".into(), "".into(), "".into(), "Lieu-dit Le Moulin".into())
+        );
+    }
+
+    #[test]
+    fn voie_du_geocodeur_conservee() {
+        let d = decomposer_resultat("5 Rue du Col");
+        assert_eq!((d.numero.as_str(), d.type_voie.as_str()), ("5", "Rue"));
+        assert_eq!(d.nom, "du Col");
+        let d = decomposer_resultat("12bis Rue du Gal Leclerc");
+        assert_eq!((d.numero.as_str(), d.indice.as_str()), ("12", "bis"));
+        assert_eq!(d.nom, "du Gal Leclerc");
+    }
+
+    #[test]
+    fn abreviations_connues_comme_types() {
+        for (abrege, complet) in ABREVIATIONS_VOIE {
+            assert!(est_type_voie(abrege), "{abrege}");
+            let premier = normaliser(complet);
+            let premier = premier.split(' ').next().unwrap_or_default();
+            assert!(TYPES_VOIE.contains(&premier), "{complet}");
+        }
+    }
+}